@@ -0,0 +1,2 @@
+-- The bundled SQLite can't drop columns, score and created_utc are left on links --
+DROP TABLE chat_settings;
@@ -0,0 +1,6 @@
+ALTER TABLE links ADD COLUMN score INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE links ADD COLUMN created_utc BIGINT NOT NULL DEFAULT 0;
+CREATE TABLE IF NOT EXISTS chat_settings(
+    chat_id BIGINT PRIMARY KEY NOT NULL,
+    selection_mode TEXT NOT NULL DEFAULT 'uniform'
+);
@@ -0,0 +1 @@
+-- The bundled SQLite can't drop columns, permalink is left on links --
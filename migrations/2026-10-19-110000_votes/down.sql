@@ -0,0 +1,2 @@
+DROP TRIGGER links_votes_ad;
+DROP TABLE votes;
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS votes(
+    link_id INTEGER NOT NULL REFERENCES links(id),
+    user_id BIGINT NOT NULL,
+    value INTEGER NOT NULL,
+    voted_at BIGINT NOT NULL,
+    PRIMARY KEY(link_id, user_id)
+);
+CREATE INDEX IF NOT EXISTS idx_votes_voted_at ON votes(voted_at);
+CREATE TRIGGER links_votes_ad AFTER DELETE ON links BEGIN
+  DELETE FROM votes WHERE link_id = old.id;
+END;
@@ -0,0 +1 @@
+-- The bundled SQLite can't drop columns, max_album_size is left on chat_settings --
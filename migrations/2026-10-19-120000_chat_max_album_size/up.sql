@@ -0,0 +1 @@
+ALTER TABLE chat_settings ADD COLUMN max_album_size INTEGER NOT NULL DEFAULT 5;
@@ -0,0 +1,2 @@
+-- The bundled SQLite can't drop columns, subreddit and media_type are left on links --
+DROP INDEX links_subreddit_idx;
@@ -0,0 +1,10 @@
+ALTER TABLE links ADD COLUMN subreddit TEXT;
+ALTER TABLE links ADD COLUMN media_type TEXT;
+UPDATE links SET subreddit = lower(substr(permalink, 4, instr(substr(permalink, 4), '/') - 1))
+  WHERE permalink LIKE '/r/%/%';
+UPDATE links SET media_type = CASE
+    WHEN lower(link) LIKE '%.png' THEN 'png'
+    WHEN lower(link) LIKE '%.jpg' OR lower(link) LIKE '%.jpeg' THEN 'jpg'
+    WHEN lower(link) LIKE '%.gif' THEN 'gif'
+  END;
+CREATE INDEX links_subreddit_idx ON links(subreddit);
@@ -0,0 +1 @@
+DROP TABLE links_title_vocab;
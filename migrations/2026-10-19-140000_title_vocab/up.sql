@@ -0,0 +1 @@
+CREATE VIRTUAL TABLE links_title_vocab USING fts5vocab(links_title_idx, row);
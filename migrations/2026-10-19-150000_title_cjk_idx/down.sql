@@ -0,0 +1,2 @@
+DROP TRIGGER links_cjk_ad;
+DROP TABLE links_cjk_idx;
@@ -0,0 +1,5 @@
+-- Titles with their CJK characters split in bigrams, filled by Database::index_cjk_titles --
+CREATE VIRTUAL TABLE links_cjk_idx USING fts5(grams);
+CREATE TRIGGER links_cjk_ad AFTER DELETE ON links BEGIN
+  DELETE FROM links_cjk_idx WHERE rowid = old.id;
+END;
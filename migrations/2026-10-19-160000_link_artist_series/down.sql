@@ -0,0 +1 @@
+-- The bundled SQLite can't drop columns, artist and series are left on links --
@@ -0,0 +1,24 @@
+ALTER TABLE links ADD COLUMN artist TEXT;
+ALTER TABLE links ADD COLUMN series TEXT;
+
+-- Same rules as scrapper::title_tags: "Title [Artist] (Series)", the series being looked for after the artist --
+CREATE TEMPORARY TABLE artist_positions AS
+  SELECT id, title, instr(title, '[') AS open, instr(substr(title, instr(title, '[') + 1), ']') AS length
+  FROM links;
+CREATE TEMPORARY TABLE title_rests AS
+  SELECT id,
+    CASE WHEN open > 0 AND length > 0 THEN nullif(trim(substr(title, open + 1, length - 1)), '') END AS artist,
+    CASE WHEN open > 0 AND length > 0 THEN substr(title, open + length + 1) ELSE title END AS rest
+  FROM artist_positions;
+CREATE TEMPORARY TABLE title_tags AS
+  SELECT id, artist,
+    CASE WHEN instr(rest, '(') > 0 AND instr(substr(rest, instr(rest, '(') + 1), ')') > 0
+      THEN nullif(trim(substr(rest, instr(rest, '(') + 1, instr(substr(rest, instr(rest, '(') + 1), ')') - 1)), '')
+    END AS series
+  FROM title_rests;
+UPDATE links SET
+  artist = (SELECT artist FROM title_tags WHERE title_tags.id = links.id),
+  series = (SELECT series FROM title_tags WHERE title_tags.id = links.id);
+DROP TABLE artist_positions;
+DROP TABLE title_rests;
+DROP TABLE title_tags;
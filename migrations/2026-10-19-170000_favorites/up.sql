@@ -0,0 +1,9 @@
+-- link_id isn't a foreign key, favorites are kept when their link is purged --
+CREATE TABLE IF NOT EXISTS favorites(
+    user_id BIGINT NOT NULL,
+    link_id INTEGER NOT NULL,
+    link TEXT NOT NULL,
+    title TEXT NOT NULL,
+    added_at BIGINT NOT NULL,
+    PRIMARY KEY(user_id, link_id)
+);
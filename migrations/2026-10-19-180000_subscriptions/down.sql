@@ -0,0 +1,2 @@
+-- The bundled SQLite can't drop columns, utc_offset is left on chat_settings --
+DROP TABLE subscriptions;
@@ -0,0 +1,8 @@
+ALTER TABLE chat_settings ADD COLUMN utc_offset INTEGER NOT NULL DEFAULT 0;
+CREATE TABLE IF NOT EXISTS subscriptions(
+    chat_id BIGINT NOT NULL PRIMARY KEY,
+    time_of_day INTEGER NOT NULL,
+    query TEXT NOT NULL,
+    next_at BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_subscriptions_next_at ON subscriptions(next_at);
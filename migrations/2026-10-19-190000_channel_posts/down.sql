@@ -0,0 +1,2 @@
+DROP TRIGGER links_channel_posts_ad;
+DROP TABLE channel_posts;
@@ -0,0 +1,14 @@
+-- The ids of the purged links get reused, so the posts are told apart by their link --
+CREATE TABLE IF NOT EXISTS channel_posts(
+    channel_id BIGINT NOT NULL,
+    link_id INTEGER NOT NULL,
+    link TEXT NOT NULL,
+    queued_at BIGINT NOT NULL,
+    posted_at BIGINT,
+    failures INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY(channel_id, link)
+);
+-- Posted links are kept so that they are never posted again --
+CREATE TRIGGER links_channel_posts_ad AFTER DELETE ON links BEGIN
+  DELETE FROM channel_posts WHERE link_id = old.id AND link = old.link AND posted_at IS NULL;
+END;
@@ -0,0 +1 @@
+-- The bundled SQLite can't drop columns, language is left on chat_settings --
@@ -0,0 +1 @@
+-- The bundled SQLite can't drop columns, commands_per_minute is left on chat_settings --
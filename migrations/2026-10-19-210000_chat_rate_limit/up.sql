@@ -0,0 +1 @@
+ALTER TABLE chat_settings ADD COLUMN commands_per_minute INTEGER;
@@ -0,0 +1 @@
+-- The bundled SQLite can't drop columns, suspect_at is left on links --
@@ -0,0 +1 @@
+-- The bundled SQLite can't drop columns, file_id is left on links --
@@ -0,0 +1 @@
+ALTER TABLE links ADD COLUMN file_id TEXT;
@@ -0,0 +1,2 @@
+-- The bundled SQLite can't drop columns, quarantined_at and send_failures are left on links --
+UPDATE links SET suspect_at = quarantined_at;
@@ -0,0 +1,6 @@
+-- Links whose image couldn't be sent were left out at once, they are now quarantined after
+-- several failures, until --purge confirms or restores them --
+ALTER TABLE links ADD COLUMN quarantined_at BIGINT;
+ALTER TABLE links ADD COLUMN send_failures INTEGER NOT NULL DEFAULT 0;
+-- The quarantine replaces suspect_at, the bundled SQLite can't drop it so it is cleared --
+UPDATE links SET quarantined_at = suspect_at, suspect_at = NULL;
@@ -0,0 +1 @@
+DROP TABLE mirrored_images;
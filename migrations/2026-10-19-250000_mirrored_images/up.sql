@@ -0,0 +1,15 @@
+-- Copies of the images in the mirror directory, named by the SHA-256 of their content, so
+-- links to the same image share a file. The copies of the images whose host deleted them are
+-- the only ones left, they aren't evicted --
+CREATE TABLE IF NOT EXISTS mirrored_images(
+    link_id INTEGER PRIMARY KEY NOT NULL,
+    sha256 TEXT NOT NULL,
+    path TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    mime_type TEXT NOT NULL,
+    mirrored_at BIGINT NOT NULL,
+    last_sent_at BIGINT NOT NULL,
+    origin_gone_at BIGINT
+);
+CREATE INDEX IF NOT EXISTS mirrored_images_sha256_idx ON mirrored_images(sha256);
+CREATE INDEX IF NOT EXISTS mirrored_images_last_sent_at_idx ON mirrored_images(last_sent_at);
@@ -0,0 +1,4 @@
+DROP TRIGGER links_duplicates_aq;
+DROP TRIGGER links_duplicates_ad;
+DROP INDEX links_duplicate_of_idx;
+-- The bundled SQLite can't drop columns, image_hash and duplicate_of are left on links --
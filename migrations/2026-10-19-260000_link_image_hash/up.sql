@@ -0,0 +1,22 @@
+-- Perceptual hash of the image, to find the reposts of an image under another link or title.
+-- A repost points to the copy that is kept, and is left out of the searches --
+ALTER TABLE links ADD COLUMN image_hash BIGINT;
+ALTER TABLE links ADD COLUMN duplicate_of INTEGER;
+CREATE INDEX links_duplicate_of_idx ON links(duplicate_of);
+-- The reposts of a deleted or quarantined link point to their best scored copy instead, which is
+-- kept in its place --
+CREATE TRIGGER links_duplicates_ad AFTER DELETE ON links BEGIN
+  UPDATE links SET duplicate_of = (
+    SELECT id FROM links WHERE duplicate_of = old.id AND quarantined_at IS NULL
+    ORDER BY score DESC, id LIMIT 1
+  ) WHERE duplicate_of = old.id;
+  UPDATE links SET duplicate_of = NULL WHERE duplicate_of = id;
+END;
+CREATE TRIGGER links_duplicates_aq AFTER UPDATE OF quarantined_at ON links
+WHEN old.quarantined_at IS NULL AND new.quarantined_at IS NOT NULL BEGIN
+  UPDATE links SET duplicate_of = (
+    SELECT id FROM links WHERE duplicate_of = old.id AND quarantined_at IS NULL
+    ORDER BY score DESC, id LIMIT 1
+  ) WHERE duplicate_of = old.id;
+  UPDATE links SET duplicate_of = NULL WHERE duplicate_of = id;
+END;
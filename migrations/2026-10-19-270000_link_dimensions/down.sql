@@ -0,0 +1,2 @@
+-- The bundled SQLite can't drop columns, width, height and byte_size are left on links, and
+-- min_resolution on chat_settings --
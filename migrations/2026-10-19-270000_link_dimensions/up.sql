@@ -0,0 +1,6 @@
+-- Size of the images, from Reddit's preview or the header of the downloaded image --
+ALTER TABLE links ADD COLUMN width INTEGER;
+ALTER TABLE links ADD COLUMN height INTEGER;
+ALTER TABLE links ADD COLUMN byte_size BIGINT;
+-- Smallest width and height of the images sent to the chat, NULL for any size --
+ALTER TABLE chat_settings ADD COLUMN min_resolution INTEGER;
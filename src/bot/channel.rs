@@ -0,0 +1,93 @@
+//! Posting of the newly scraped images to channels
+
+use super::outbox::Outbox;
+use super::photo;
+use crate::db::{self, model::Link};
+use crate::utils::unix_now;
+use crate::{Result, YuribotError};
+
+use std::time::Duration;
+
+use telegram_bot::types::ChatId;
+use tokio::time;
+
+pub struct ChannelConfig {
+    pub channel_ids: Vec<i64>,
+    pub post_interval: Duration,
+    pub hashtags: Vec<String>,
+}
+
+fn caption(link: &Link, hashtags: &[String]) -> String {
+    let mut caption = format!("{}\n{}", link.title, link.source_url());
+    if !hashtags.is_empty() {
+        caption.push('\n');
+        let hashtags: Vec<String> = hashtags
+            .iter()
+            .map(|tag| format!("#{}", tag.trim_start_matches('#')))
+            .collect();
+        caption.push_str(&hashtags.join(" "));
+    }
+    caption
+}
+
+/// Number of times the image of a queued link fails to be posted before it is skipped
+const MAX_POST_FAILURES: i32 = 3;
+
+/// Posts the next queued link of the channel, if there is one
+async fn post_next(
+    db_pool: &db::DbPool,
+    outbox: &Outbox,
+    channel_id: i64,
+    hashtags: &[String],
+) -> Result<()> {
+    let link = match db_pool
+        .get()
+        .await?
+        .next_channel_post(channel_id, MAX_POST_FAILURES)?
+    {
+        Some(link) => link,
+        None => return Ok(()),
+    };
+    info!(
+        "Posting image to channel {}\n\t{}: {}",
+        channel_id, link.title, link.link
+    );
+    let caption = caption(&link, hashtags);
+    let result = photo::send_photo(
+        db_pool,
+        outbox,
+        ChatId::new(channel_id),
+        &link,
+        &caption,
+        None,
+    )
+    .await;
+    // Marked once sent, a post whose result is lost can be posted twice. Only the failures of
+    // the image count, the link is posted again after the other errors
+    let database = db_pool.get().await?;
+    match result {
+        Ok(()) => database.mark_channel_post(channel_id, &link.link, unix_now())?,
+        Err(YuribotError::UnavailableImageError(_)) => {
+            database.record_channel_post_failure(channel_id, &link.link)?
+        }
+        Err(_) => {}
+    }
+    result
+}
+
+/// Posts one queued link to each channel every `post_interval`
+pub async fn run_channel_poster(db_pool: db::DbPool, outbox: Outbox, config: ChannelConfig) {
+    if config.channel_ids.is_empty() {
+        return futures::future::pending().await;
+    }
+    info!("posting to channels {:?}", config.channel_ids);
+    let mut interval = time::interval(config.post_interval);
+    loop {
+        interval.tick().await;
+        for &channel_id in &config.channel_ids {
+            if let Err(e) = post_next(&db_pool, &outbox, channel_id, &config.hashtags).await {
+                error!("failed to post to channel {}: {}", channel_id, e);
+            }
+        }
+    }
+}
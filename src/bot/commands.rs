@@ -0,0 +1,555 @@
+//! Registry of the bot commands, used to parse them, to write /help and to fill the command
+//! menu of the Telegram clients
+
+use super::locale::{tr, Lang};
+use super::message;
+use super::outbox::Outbox;
+use super::requests::BotCommand;
+use crate::utils::utf8_pos_from_utf16;
+use crate::Result;
+
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::time::Duration;
+
+use serde::Deserialize;
+use telegram_bot::types::{
+    ChatMemberStatus, GetChatMember, Message, MessageEntityKind, MessageKind, UserId,
+};
+
+/// Fake sender Telegram puts in the messages an anonymous administrator sends on behalf of the
+/// group. The group itself is in `sender_chat`, which telegram-bot doesn't read
+const GROUP_ANONYMOUS_BOT_ID: i64 = 1087968824;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandKind {
+    Start,
+    Help,
+    More,
+    Artist,
+    Series,
+    Favs,
+    Subscribe,
+    Unsubscribe,
+    Count,
+    Top,
+    Settings,
+    Version,
+}
+
+/// Who can use a command
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Permission {
+    Everyone,
+    /// only the administrators in groups, anyone in private chats
+    ChatAdmin,
+}
+
+/// Kind of value an argument takes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArgKind {
+    /// a leading number of images
+    Count,
+    /// a single word
+    Word,
+    /// the rest of the arguments, like a search
+    Text,
+    /// one of these words
+    Choice(&'static [&'static str]),
+}
+
+impl ArgKind {
+    fn accepts(self, word: &str) -> bool {
+        match self {
+            ArgKind::Count => message::split_count(word).0.is_some(),
+            ArgKind::Word | ArgKind::Text => !word.is_empty(),
+            ArgKind::Choice(choices) => choices.contains(&word),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ArgSpec {
+    /// name, which is also written in the usage line
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub required: bool,
+}
+
+impl ArgSpec {
+    fn optional(name: &'static str, kind: ArgKind) -> Self {
+        ArgSpec {
+            name,
+            kind,
+            required: false,
+        }
+    }
+
+    fn required(name: &'static str, kind: ArgKind) -> Self {
+        ArgSpec {
+            name,
+            kind,
+            required: true,
+        }
+    }
+
+    fn count() -> Self {
+        ArgSpec::optional("count", ArgKind::Count)
+    }
+
+    fn usage(&self) -> String {
+        let value = match self.kind {
+            ArgKind::Choice(choices) => choices.join("|"),
+            _ => self.name.to_owned(),
+        };
+        if self.required {
+            format!("<{}>", value)
+        } else {
+            format!("[{}]", value)
+        }
+    }
+}
+
+/// Arguments of a command, by name
+#[derive(Debug, Default, PartialEq)]
+pub struct Args(Vec<(&'static str, String)>);
+
+impl Args {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(arg_name, _)| *arg_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn count(&self) -> Option<usize> {
+        self.get("count").and_then(|count| count.parse().ok())
+    }
+}
+
+/// The first word of `text` and the rest
+fn split_word(text: &str) -> (&str, &str) {
+    match text.find(char::is_whitespace) {
+        Some(i) => (&text[..i], text[i..].trim_start()),
+        None => (text, ""),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CommandSpec {
+    pub kind: CommandKind,
+    /// name, without the /
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub args: Vec<ArgSpec>,
+    /// key of the description in the message catalog
+    pub description: &'static str,
+    pub permission: Permission,
+}
+
+impl CommandSpec {
+    fn new(kind: CommandKind, name: &str, args: Vec<ArgSpec>, description: &'static str) -> Self {
+        CommandSpec {
+            kind,
+            name: name.to_owned(),
+            aliases: Vec::new(),
+            args,
+            description,
+            permission: Permission::Everyone,
+        }
+    }
+
+    fn alias(mut self, alias: &str) -> Self {
+        self.aliases.push(alias.to_owned());
+        self
+    }
+
+    fn admin_only(mut self) -> Self {
+        self.permission = Permission::ChatAdmin;
+        self
+    }
+
+    pub fn usage(&self) -> String {
+        let mut usage = format!("/{}", self.name);
+        for arg in &self.args {
+            usage.push(' ');
+            usage.push_str(&arg.usage());
+        }
+        usage
+    }
+
+    /// Parses the argument of the command, returns `None` if it doesn't match the command's
+    /// arguments. Each argument takes a word, except the text which takes the rest, and a
+    /// choice after the text, which is looked for at the end
+    pub fn parse_args(&self, arg: &str) -> Option<Args> {
+        // Like the payload of the /start links, the text after the others is ignored
+        if self.args.is_empty() {
+            return Some(Args::default());
+        }
+        let mut rest = arg.trim();
+        let mut args = Args::default();
+        let mut specs = &self.args[..];
+        if let [before @ .., last] = specs {
+            if matches!(last.kind, ArgKind::Choice(_))
+                && before.iter().any(|spec| spec.kind == ArgKind::Text)
+            {
+                let (start, word) = match rest.rfind(char::is_whitespace) {
+                    Some(i) => (rest[..i].trim_end(), rest[i..].trim_start()),
+                    None => ("", rest),
+                };
+                if last.kind.accepts(word) {
+                    args.0.push((last.name, word.to_owned()));
+                    rest = start;
+                } else if last.required {
+                    return None;
+                }
+                specs = before;
+            }
+        }
+        for spec in specs {
+            let (value, others) = match spec.kind {
+                ArgKind::Text => (rest, ""),
+                _ => split_word(rest),
+            };
+            if spec.kind.accepts(value) {
+                args.0.push((spec.name, value.to_owned()));
+                rest = others;
+            } else if spec.required {
+                return None;
+            }
+        }
+        if rest.is_empty() {
+            Some(args)
+        } else {
+            None
+        }
+    }
+}
+
+/// Name and aliases replacing the default ones of a command, from the config
+#[derive(Debug, Deserialize)]
+pub struct CommandNames {
+    pub name: Option<String>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+/// Whether Telegram accepts the name for a command
+fn is_valid_name(name: &str) -> bool {
+    (1..=32).contains(&name.len())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// A command found in a message
+#[derive(Debug)]
+pub struct ParsedCommand<'a> {
+    /// `None` if the command isn't registered
+    pub spec: Option<&'a CommandSpec>,
+    pub arg: message::ArgRange,
+    /// whether the command was suffixed with the name of the bot
+    pub is_directed: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Registry {
+    commands: Vec<CommandSpec>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        use CommandKind::*;
+        Registry {
+            commands: vec![
+                CommandSpec::new(
+                    More,
+                    "more",
+                    vec![ArgSpec::count(), ArgSpec::optional("search", ArgKind::Text)],
+                    "cmd-more",
+                ),
+                CommandSpec::new(
+                    Artist,
+                    "artist",
+                    vec![ArgSpec::count(), ArgSpec::required("name", ArgKind::Text)],
+                    "cmd-artist",
+                ),
+                CommandSpec::new(
+                    Series,
+                    "series",
+                    vec![ArgSpec::count(), ArgSpec::required("name", ArgKind::Text)],
+                    "cmd-series",
+                ),
+                CommandSpec::new(
+                    Favs,
+                    "favs",
+                    vec![
+                        ArgSpec::optional("list", ArgKind::Choice(&["list"])),
+                        ArgSpec::optional("page", ArgKind::Word),
+                    ],
+                    "cmd-favs",
+                )
+                .alias("favorites"),
+                CommandSpec::new(
+                    Subscribe,
+                    "subscribe",
+                    vec![
+                        ArgSpec::optional("HH:MM", ArgKind::Word),
+                        ArgSpec::optional("search", ArgKind::Text),
+                    ],
+                    "cmd-subscribe",
+                )
+                .admin_only(),
+                CommandSpec::new(Unsubscribe, "unsubscribe", vec![], "cmd-unsubscribe")
+                    .admin_only(),
+                CommandSpec::new(
+                    Count,
+                    "count",
+                    vec![ArgSpec::optional("search", ArgKind::Text)],
+                    "cmd-count",
+                ),
+                CommandSpec::new(
+                    Top,
+                    "top",
+                    vec![
+                        ArgSpec::optional("search", ArgKind::Text),
+                        ArgSpec::optional("period", ArgKind::Choice(&["week", "month", "all"])),
+                    ],
+                    "cmd-top",
+                ),
+                CommandSpec::new(
+                    Settings,
+                    "settings",
+                    vec![
+                        ArgSpec::optional("setting", ArgKind::Word),
+                        ArgSpec::optional("value", ArgKind::Word),
+                    ],
+                    "cmd-settings",
+                )
+                .admin_only(),
+                CommandSpec::new(Help, "help", vec![], "cmd-help"),
+                CommandSpec::new(Start, "start", vec![], "cmd-start"),
+                CommandSpec::new(Version, "version", vec![], "cmd-version"),
+            ],
+        }
+    }
+}
+
+impl Registry {
+    /// Default commands, renamed by `overrides`, which is keyed by the default names
+    pub fn new(overrides: &HashMap<String, CommandNames>) -> std::result::Result<Self, String> {
+        let mut registry = Registry::default();
+        // Looked up before any renaming, so that a command can take the default name of another
+        let indexes: HashMap<String, usize> = registry
+            .commands
+            .iter()
+            .enumerate()
+            .map(|(i, spec)| (spec.name.clone(), i))
+            .collect();
+        for (default_name, names) in overrides {
+            let i = *indexes
+                .get(default_name)
+                .ok_or_else(|| format!("there is no /{} command", default_name))?;
+            let spec = &mut registry.commands[i];
+            if let Some(name) = &names.name {
+                spec.name = name.clone();
+            }
+            spec.aliases = names.aliases.clone();
+        }
+        let mut seen = Vec::new();
+        for spec in &registry.commands {
+            for name in std::iter::once(&spec.name).chain(&spec.aliases) {
+                if !is_valid_name(name) {
+                    return Err(format!(
+                        "/{} isn't a valid command name, use 1 to 32 lowercase letters, \
+                         digits and _",
+                        name
+                    ));
+                }
+                if seen.contains(&name) {
+                    return Err(format!("/{} is used by several commands", name));
+                }
+                seen.push(name);
+            }
+        }
+        Ok(registry)
+    }
+
+    /// Command named `name`, or with `name` as an alias
+    pub fn find(&self, name: &str) -> Option<&CommandSpec> {
+        self.commands
+            .iter()
+            .find(|spec| spec.name == name || spec.aliases.iter().any(|alias| alias == name))
+    }
+
+    pub fn get(&self, kind: CommandKind) -> &CommandSpec {
+        self.commands
+            .iter()
+            .find(|spec| spec.kind == kind)
+            .expect("every command is registered")
+    }
+
+    pub fn parse(&self, botname: &str, message: &Message) -> Option<ParsedCommand<'_>> {
+        let (data, entities) = match message.kind {
+            MessageKind::Text {
+                ref data,
+                ref entities,
+            } => (data, entities),
+            _ => return None,
+        };
+        let entity = entities.first()?;
+        match entity.kind {
+            MessageEntityKind::BotCommand => {}
+            _ => return None,
+        };
+        if entity.offset != 0 {
+            return None;
+        }
+        let length = utf8_pos_from_utf16(data, entity.length.try_into().ok()?)?;
+        let (command, is_directed) = if data[..length].ends_with(botname) {
+            (&data[..(length - botname.len())], true)
+        } else {
+            (&data[..length], false)
+        };
+        Some(ParsedCommand {
+            spec: self.find(command.trim_start_matches('/')),
+            arg: length..data.len(),
+            is_directed,
+        })
+    }
+
+    pub fn help(&self, lang: Lang) -> String {
+        let mut help = tr(lang, "help-header", &[]);
+        help.push('\n');
+        for spec in self
+            .commands
+            .iter()
+            .filter(|spec| spec.kind != CommandKind::Start)
+        {
+            help.push_str(&format!(
+                "\n{} - {}",
+                spec.usage(),
+                tr(lang, spec.description, &[])
+            ));
+            for alias in &spec.aliases {
+                help.push_str(&tr(lang, "help-alias", &[("alias", alias)]));
+            }
+            if spec.permission == Permission::ChatAdmin {
+                help.push_str(&tr(lang, "help-admin-only", &[]));
+            }
+        }
+        help
+    }
+
+    /// Commands shown in the menu of the Telegram clients
+    pub fn bot_commands(&self, lang: Lang) -> Vec<BotCommand> {
+        self.commands
+            .iter()
+            .filter(|spec| spec.kind != CommandKind::Start)
+            .map(|spec| BotCommand::new(spec.name.clone(), tr(lang, spec.description, &[])))
+            .collect()
+    }
+}
+
+/// Whether the sender of the message can use the command
+pub async fn is_allowed(
+    outbox: &Outbox,
+    message: &Message,
+    permission: Permission,
+) -> Result<bool> {
+    if permission == Permission::Everyone
+        || message::is_private(message)
+        || message.from.id == UserId::new(GROUP_ANONYMOUS_BOT_ID)
+    {
+        return Ok(true);
+    }
+    let member = outbox
+        .send_timeout(
+            None,
+            GetChatMember::new(&message.chat, &message.from),
+            Duration::from_secs(5),
+        )
+        .await?;
+    Ok(matches!(
+        member.map(|member| member.status),
+        Some(ChatMemberStatus::Creator) | Some(ChatMemberStatus::Administrator)
+    ))
+}
+
+#[test]
+fn test_registry() {
+    let registry = Registry::default();
+    assert_eq!(CommandKind::More, registry.find("more").unwrap().kind);
+    assert_eq!(CommandKind::Favs, registry.find("favorites").unwrap().kind);
+    assert!(registry.find("nope").is_none());
+    assert_eq!(
+        "/top [search] [week|month|all]",
+        registry.get(CommandKind::Top).usage()
+    );
+    let help = registry.help(Lang::En);
+    assert!(help.contains("\n/favs [list] [page] - Sends or lists your favorites, or /favorites"));
+    assert!(!help.contains("/start"));
+    assert_eq!(
+        registry.commands.len() - 1,
+        registry.bot_commands(Lang::Fr).len()
+    );
+}
+
+#[test]
+fn test_parse_args() {
+    let registry = Registry::default();
+    let parse = |kind, arg| registry.get(kind).parse_args(arg);
+    let args = parse(CommandKind::More, "3 maid outfit").unwrap();
+    assert_eq!(Some(3), args.count());
+    assert_eq!(Some("maid outfit"), args.get("search"));
+    assert_eq!(None, parse(CommandKind::More, "").unwrap().get("search"));
+    assert_eq!(None, parse(CommandKind::Artist, "2"));
+    let args = parse(CommandKind::Top, "maid outfit  month").unwrap();
+    assert_eq!(Some("maid outfit"), args.get("search"));
+    assert_eq!(Some("month"), args.get("period"));
+    let args = parse(CommandKind::Top, "week").unwrap();
+    assert_eq!(None, args.get("search"));
+    assert_eq!(Some("week"), args.get("period"));
+    let args = parse(CommandKind::Favs, "list 2").unwrap();
+    assert_eq!(
+        (Some("list"), Some("2")),
+        (args.get("list"), args.get("page"))
+    );
+    assert_eq!(None, parse(CommandKind::Favs, "list 2 3"));
+    assert_eq!(None, parse(CommandKind::Settings, "mode top extra"));
+    assert_eq!(Some(Args::default()), parse(CommandKind::Start, "payload"));
+}
+
+#[test]
+fn test_registry_overrides() {
+    let names = |name: Option<&str>, aliases: &[&str]| CommandNames {
+        name: name.map(str::to_owned),
+        aliases: aliases.iter().map(|&alias| alias.to_owned()).collect(),
+    };
+    let mut overrides = HashMap::new();
+    overrides.insert("more".to_owned(), names(Some("yuri"), &["random"]));
+    overrides.insert("favs".to_owned(), names(None, &[]));
+    let registry = Registry::new(&overrides).unwrap();
+    assert_eq!(CommandKind::More, registry.find("yuri").unwrap().kind);
+    assert_eq!(CommandKind::More, registry.find("random").unwrap().kind);
+    assert!(registry.find("more").is_none());
+    assert!(registry.find("favorites").is_none());
+    assert_eq!("yuri", registry.get(CommandKind::More).name);
+
+    overrides.insert("top".to_owned(), names(None, &["yuri"]));
+    assert!(Registry::new(&overrides).is_err());
+    overrides.remove("top");
+    overrides.insert("nope".to_owned(), names(Some("nope"), &[]));
+    assert!(Registry::new(&overrides).is_err());
+    overrides.remove("nope");
+    overrides.insert("count".to_owned(), names(Some("Count"), &[]));
+    assert!(Registry::new(&overrides).is_err());
+
+    let mut swapped = HashMap::new();
+    swapped.insert("more".to_owned(), names(Some("yuri"), &[]));
+    swapped.insert("count".to_owned(), names(Some("more"), &[]));
+    let registry = Registry::new(&swapped).unwrap();
+    assert_eq!(CommandKind::More, registry.find("yuri").unwrap().kind);
+    assert_eq!(CommandKind::Count, registry.find("more").unwrap().kind);
+    assert!(registry.find("count").is_none());
+}
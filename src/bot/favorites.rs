@@ -0,0 +1,144 @@
+use super::commands::{Args, CommandKind, Registry};
+use super::locale::{tr, tr_count, Lang};
+use super::message;
+use super::outbox::Outbox;
+use crate::db;
+use crate::Result;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use telegram_bot::{prelude::CanReplySendMessage, types::Message};
+
+/// Number of favorites in each page of /favs list
+const PAGE_SIZE: i64 = 10;
+
+async fn reply(outbox: &Outbox, message: &Message, text: String) -> Result<()> {
+    outbox
+        .send_timeout(
+            Some(message.chat.id()),
+            message.text_reply(text).disable_preview(),
+            Duration::from_secs(5),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Sends a random favorite of the user, or tells them that all their favorites have died
+async fn send_random_favorite(
+    database: db::DbPool,
+    outbox: Outbox,
+    message: Message,
+    lang: Lang,
+) -> Result<()> {
+    let user_id = message.from.id.into();
+    let favorite = database.get().await?.random_favorite(user_id)?;
+    match favorite {
+        None => reply(&outbox, &message, tr(lang, "favs-none", &[])).await,
+        Some((_, Some(link))) => {
+            super::send_link(
+                &database,
+                &outbox,
+                message.chat.id(),
+                Some(&message.from),
+                link,
+                "",
+                lang,
+            )
+            .await
+        }
+        Some((favorite, None)) => {
+            let text = tr(
+                lang,
+                "favs-gone",
+                &[("title", &favorite.title), ("link", &favorite.link)],
+            );
+            reply(&outbox, &message, text).await
+        }
+    }
+}
+
+async fn send_favorites_page(
+    database: db::DbPool,
+    outbox: Outbox,
+    message: Message,
+    page: i64,
+    name: &str,
+    lang: Lang,
+) -> Result<()> {
+    if !message::is_private(&message) {
+        return reply(&outbox, &message, tr(lang, "favs-private", &[])).await;
+    }
+    let user_id = message.from.id.into();
+    let (count, pages, favorites) = {
+        let database = database.get().await?;
+        let count = database.count_favorites(user_id)?;
+        let pages = (count + PAGE_SIZE - 1) / PAGE_SIZE;
+        // Checked before the offset, which a huge page would overflow
+        let favorites = if page <= pages {
+            database.favorites_page(user_id, (page - 1) * PAGE_SIZE, PAGE_SIZE)?
+        } else {
+            Vec::new()
+        };
+        (count, pages, favorites)
+    };
+    if favorites.is_empty() {
+        let text = tr_count(lang, "favs-no-page", count, &[("page", &page)]);
+        return reply(&outbox, &message, text).await;
+    }
+    let mut text = tr(lang, "favs-page", &[("page", &page), ("pages", &pages)]);
+    text.push('\n');
+    for (i, (favorite, alive)) in favorites.iter().enumerate() {
+        let number = (page - 1) * PAGE_SIZE + i as i64 + 1;
+        if *alive {
+            text.push_str(&format!(
+                "\n{}. {}\n{}",
+                number, favorite.title, favorite.link
+            ));
+        } else {
+            text.push_str(&format!(
+                "\n{}. 💀 {} {}\n{}",
+                number,
+                favorite.title,
+                tr(lang, "favs-dead", &[]),
+                favorite.link
+            ));
+        }
+    }
+    if page < pages {
+        text.push_str("\n\n");
+        text.push_str(&tr(
+            lang,
+            "favs-next",
+            &[("command", &name), ("page", &(page + 1))],
+        ));
+    }
+    reply(&outbox, &message, text).await
+}
+
+pub async fn handle_favs(
+    database: db::DbPool,
+    outbox: Outbox,
+    message: Message,
+    args: Args,
+    commands: Arc<Registry>,
+) -> Result<()> {
+    let spec = commands.get(CommandKind::Favs);
+    let lang = super::chat_lang(&database, message.chat.id(), Some(&message.from)).await?;
+    match (args.get("list"), args.get("page")) {
+        (None, None) => send_random_favorite(database, outbox, message, lang).await,
+        (Some(_), page) => match page.map_or(Ok(1), str::parse) {
+            Ok(page) if page > 0 => {
+                send_favorites_page(database, outbox, message, page, &spec.name, lang).await
+            }
+            _ => {
+                let usage = format!("/{} list [page]", spec.name);
+                reply(&outbox, &message, tr(lang, "usage", &[("usage", &usage)])).await
+            }
+        },
+        (None, Some(_)) => {
+            let usage = spec.usage();
+            reply(&outbox, &message, tr(lang, "usage", &[("usage", &usage)])).await
+        }
+    }
+}
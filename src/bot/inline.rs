@@ -0,0 +1,68 @@
+use super::outbox::Outbox;
+use super::requests::{AnswerInlineQuery, InlinePhoto};
+use crate::db;
+use crate::rate_limit::RateLimiter;
+use crate::search;
+use crate::Result;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use telegram_bot::types::InlineQuery;
+
+/// Number of results in each page of inline results
+const PAGE_SIZE: i64 = 20;
+
+/// Seconds the empty answer to a rate limited query is cached, so that the user can retry soon
+const RATE_LIMITED_CACHE_TIME: i64 = 5;
+
+pub async fn handle_inline_query(
+    database: db::DbPool,
+    outbox: Outbox,
+    limiter: Arc<RateLimiter<i64>>,
+    cache_time: i64,
+    query: InlineQuery,
+) -> Result<()> {
+    if !limiter.check(query.from.id.into()) {
+        debug!("rate limited inline query from {:?}", query.from.username);
+        // Telegram keeps showing a spinner until the query is answered
+        outbox
+            .send_timeout(
+                None,
+                AnswerInlineQuery::new(query.id, Vec::new(), RATE_LIMITED_CACHE_TIME, None)
+                    .personal(),
+                Duration::from_secs(5),
+            )
+            .await?;
+        return Ok(());
+    }
+    let offset: i64 = query.offset.parse().unwrap_or(0);
+    // Malformed searches get no results, there is no way to explain the error inline
+    let links = match search::parse(&query.query) {
+        Ok(search) => database
+            .get()
+            .await?
+            .search_page(&search, offset, PAGE_SIZE)?,
+        Err(_) => Vec::new(),
+    };
+    let next_offset = if links.len() as i64 == PAGE_SIZE {
+        Some((offset + PAGE_SIZE).to_string())
+    } else {
+        None
+    };
+    let results = links
+        .into_iter()
+        .map(|link| match link.file_id {
+            Some(file_id) => InlinePhoto::cached(link.id.to_string(), file_id, link.title),
+            None => InlinePhoto::new(link.id.to_string(), link.link, link.title),
+        })
+        .collect();
+    outbox
+        .send_timeout(
+            None,
+            AnswerInlineQuery::new(query.id, results, cache_time, next_offset),
+            Duration::from_secs(5),
+        )
+        .await?;
+    Ok(())
+}
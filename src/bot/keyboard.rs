@@ -0,0 +1,149 @@
+use super::locale::{tr, Lang};
+use crate::db::model;
+
+use telegram_bot::types::{InlineKeyboardButton, InlineKeyboardMarkup};
+
+/// Telegram rejects buttons with more than 64 bytes of callback data
+const MAX_DATA_LEN: usize = 64;
+
+/// Action triggered by pressing a button under a sent image
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    More { query: String },
+    Vote { link_id: i32, up: bool },
+    Favorite { link_id: i32 },
+}
+
+impl Action {
+    /// Returns `None` when the data doesn't fit in a button, which happens with long queries
+    pub fn to_data(&self) -> Option<String> {
+        let data = match self {
+            Action::More { query } => format!("more:{}", query),
+            Action::Vote { link_id, up } => {
+                format!("vote:{}:{}", link_id, if *up { "up" } else { "down" })
+            }
+            Action::Favorite { link_id } => format!("fav:{}", link_id),
+        };
+        if data.len() <= MAX_DATA_LEN {
+            Some(data)
+        } else {
+            None
+        }
+    }
+
+    pub fn from_data(data: &str) -> Option<Self> {
+        let (kind, rest) = data.split_at(data.find(':')?);
+        let rest = &rest[1..];
+        match kind {
+            "more" => Some(Action::More {
+                query: rest.to_owned(),
+            }),
+            "vote" => {
+                let (link_id, direction) = rest.split_at(rest.find(':')?);
+                let up = match &direction[1..] {
+                    "up" => true,
+                    "down" => false,
+                    _ => return None,
+                };
+                Some(Action::Vote {
+                    link_id: link_id.parse().ok()?,
+                    up,
+                })
+            }
+            "fav" => Some(Action::Favorite {
+                link_id: rest.parse().ok()?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The "another" button is left out when the query is too long to fit in its data
+pub fn link_keyboard(link: &model::Link, query: &str, lang: Lang) -> InlineKeyboardMarkup {
+    let actions = vec![
+        (
+            tr(lang, "button-another", &[]),
+            Action::More {
+                query: query.to_owned(),
+            },
+        ),
+        (
+            "👍".to_owned(),
+            Action::Vote {
+                link_id: link.id,
+                up: true,
+            },
+        ),
+        (
+            "👎".to_owned(),
+            Action::Vote {
+                link_id: link.id,
+                up: false,
+            },
+        ),
+        ("⭐".to_owned(), Action::Favorite { link_id: link.id }),
+    ];
+    let mut row: Vec<InlineKeyboardButton> = actions
+        .into_iter()
+        .filter_map(|(text, action)| {
+            action
+                .to_data()
+                .map(|data| InlineKeyboardButton::callback(text, data))
+        })
+        .collect();
+    row.push(InlineKeyboardButton::url(
+        tr(lang, "button-source", &[]),
+        link.source_url(),
+    ));
+    let mut keyboard = InlineKeyboardMarkup::new();
+    keyboard.add_row(row);
+    keyboard
+}
+
+/// Button retrying a search that had no result with a corrected one, if it fits in the button
+pub fn suggestion_keyboard(suggestion: &str) -> Option<InlineKeyboardMarkup> {
+    let data = Action::More {
+        query: suggestion.to_owned(),
+    }
+    .to_data()?;
+    let mut keyboard = InlineKeyboardMarkup::new();
+    keyboard.add_row(vec![InlineKeyboardButton::callback(
+        format!("🔍 {}", suggestion),
+        data,
+    )]);
+    Some(keyboard)
+}
+
+#[test]
+fn test_action_data_roundtrip() {
+    let actions = vec![
+        Action::More {
+            query: "maid outfit".to_owned(),
+        },
+        Action::More {
+            query: String::new(),
+        },
+        Action::Vote {
+            link_id: 42,
+            up: false,
+        },
+        Action::Favorite { link_id: 7 },
+    ];
+    for action in actions {
+        let data = action.to_data().unwrap();
+        assert_eq!(Some(&action), Action::from_data(&data).as_ref());
+    }
+    assert_eq!(None, Action::from_data("vote:42:sideways"));
+    assert_eq!(None, Action::from_data("unknown"));
+}
+
+#[test]
+fn test_long_query_has_no_data() {
+    let action = |query: String| Action::More { query };
+    assert_eq!(None, action("百合".repeat(20)).to_data());
+    let fitting = "a".repeat(MAX_DATA_LEN - "more:".len());
+    assert_eq!(
+        Some(format!("more:{}", fitting)),
+        action(fitting.clone()).to_data()
+    );
+}
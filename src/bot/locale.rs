@@ -0,0 +1,528 @@
+//! Catalog of the messages of the bot, in each language it speaks
+
+use crate::search::SearchError;
+
+use std::fmt::Display;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lang {
+    En,
+    Fr,
+}
+
+impl Lang {
+    pub const ALL: [Lang; 2] = [Lang::En, Lang::Fr];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Lang::En => "en",
+            Lang::Fr => "fr",
+        }
+    }
+
+    /// Language of a tag like `fr` or `fr-CA`, if the bot speaks it
+    pub fn from_code(code: &str) -> Option<Lang> {
+        let code = code.split(['-', '_']).next()?;
+        Lang::ALL
+            .iter()
+            .find(|lang| lang.as_str().eq_ignore_ascii_case(code))
+            .copied()
+    }
+
+    /// Language chosen in the chat's settings, or else the user's, or else English
+    pub fn resolve(chat_language: Option<&str>, user_language: Option<&str>) -> Lang {
+        chat_language
+            .and_then(Lang::from_code)
+            .or_else(|| user_language.and_then(Lang::from_code))
+            .unwrap_or(Lang::En)
+    }
+
+    fn catalog(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Lang::En => EN,
+            Lang::Fr => FR,
+        }
+    }
+
+    /// Whether `count` takes the singular, `.one` variant of the messages
+    fn is_singular(&self, count: i64) -> bool {
+        match self {
+            Lang::En => count == 1,
+            Lang::Fr => count == 0 || count == 1,
+        }
+    }
+}
+
+fn lookup(lang: Lang, key: &str) -> Option<&'static str> {
+    lang.catalog()
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, message)| *message)
+}
+
+/// Message `key` in the language, with each `{name}` replaced by the value of `name`
+pub fn tr(lang: Lang, key: &str, args: &[(&str, &(dyn Display + Sync))]) -> String {
+    let template = match lookup(lang, key).or_else(|| lookup(Lang::En, key)) {
+        Some(template) => template,
+        None => {
+            warn!("no message {} in the catalog", key);
+            return key.to_owned();
+        }
+    };
+    // In one pass, so that values containing braces are left alone
+    let mut message = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        message.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after.find('}').and_then(|end| {
+            let name = &after[..end];
+            args.iter()
+                .find(|(arg, _)| *arg == name)
+                .map(|(_, value)| (end, value))
+        });
+        match value {
+            Some((end, value)) => {
+                message.push_str(&value.to_string());
+                rest = &after[end + 1..];
+            }
+            None => {
+                message.push('{');
+                rest = after;
+            }
+        }
+    }
+    message.push_str(rest);
+    message
+}
+
+/// Like `tr`, with a `{count}` that picks between `key` and its singular `key.one`
+pub fn tr_count(
+    lang: Lang,
+    key: &str,
+    count: i64,
+    args: &[(&str, &(dyn Display + Sync))],
+) -> String {
+    let singular = format!("{}.one", key);
+    let key = if lang.is_singular(count) && lookup(lang, &singular).is_some() {
+        &singular
+    } else {
+        key
+    };
+    let mut all_args: Vec<(&str, &(dyn Display + Sync))> = vec![("count", &count)];
+    all_args.extend_from_slice(args);
+    tr(lang, key, &all_args)
+}
+
+pub fn search_error(lang: Lang, error: &SearchError) -> String {
+    match error {
+        SearchError::UnclosedQuote => tr(lang, "search-unclosed-quote", &[]),
+        SearchError::EmptyTerm => tr(lang, "search-empty-term", &[]),
+        SearchError::MisplacedOr => tr(lang, "search-misplaced-or", &[]),
+        SearchError::OnlyExclusions => tr(lang, "search-only-exclusions", &[]),
+        SearchError::ExcludedFilter => tr(lang, "search-excluded-filter", &[]),
+        SearchError::MissingFilterValue(filter) => {
+            tr(lang, "search-missing-value", &[("filter", filter)])
+        }
+        SearchError::InvalidDate(value) => tr(lang, "search-invalid-date", &[("value", value)]),
+        SearchError::InvalidScore(value) => tr(lang, "search-invalid-score", &[("value", value)]),
+        SearchError::UnknownMediaType(value) => {
+            tr(lang, "search-unknown-type", &[("value", value)])
+        }
+        SearchError::UnknownOrientation(value) => {
+            tr(lang, "search-unknown-orientation", &[("value", value)])
+        }
+        SearchError::InvalidResolution(value) => {
+            tr(lang, "search-invalid-resolution", &[("value", value)])
+        }
+    }
+}
+
+const EN: &[(&str, &str)] = &[
+    ("language", "English"),
+    ("no-image", "There is no image in the database for this. Sorry :("),
+    (
+        "no-image-suggestion",
+        "There is no image in the database for this. Did you mean \"{suggestion}\"?",
+    ),
+    ("usage", "Usage: {usage}"),
+    ("vote-thanks", "Thanks for voting!"),
+    ("favorite-added", "Added to your favorites ⭐"),
+    ("favorite-removed", "Removed from your favorites"),
+    ("favorite-gone", "This image isn't in the database anymore"),
+    ("top-none", "No image has been voted for in this period yet"),
+    ("count", "There are {count} links in the database for this query"),
+    ("count.one", "There is {count} link in the database for this query"),
+    (
+        "settings-current",
+        "Current settings:\nmode: {mode}\nalbum: {album}\ntimezone: {timezone}\nlanguage: {language}\ncommands per minute: {limit}\nminimum resolution: {resolution}",
+    ),
+    ("settings-language-auto", "auto, the language of each user"),
+    ("settings-mode-set", "Selection mode set to {mode}"),
+    ("settings-album-set", "Albums are now limited to {count} images"),
+    ("settings-album-set.one", "Albums are now limited to {count} image"),
+    ("settings-timezone-set", "Timezone set to {timezone}"),
+    ("settings-language-set", "I will now reply in English"),
+    (
+        "settings-resolution-set",
+        "Images smaller than {resolution}×{resolution} pixels won't be sent anymore",
+    ),
+    ("settings-resolution-any", "any"),
+    ("settings-resolution-any-set", "Images of any size will now be sent"),
+    (
+        "settings-language-auto-set",
+        "I will now reply in the language of each user",
+    ),
+    (
+        "settings-usage",
+        "Usage:\n/{command} mode <{modes}>\n/{command} album <1-{max}>\n/{command} timezone <UTC offset, like +02:00>\n/{command} language <{languages}|auto>\n/{command} limit <1-{max_limit}|default>\n/{command} resolution <pixels|any>",
+    ),
+    ("settings-limit-set", "This chat can now send {count} commands per minute"),
+    ("settings-limit-set.one", "This chat can now send {count} command per minute"),
+    (
+        "settings-limit-default",
+        "This chat now has the default limit of {count} commands per minute",
+    ),
+    (
+        "settings-limit-default.one",
+        "This chat now has the default limit of {count} command per minute",
+    ),
+    (
+        "cooldown",
+        "Too many commands were sent, wait a minute before the next one",
+    ),
+    ("unrecognized", "Unrecognized command, see /{help}"),
+    ("start", "Hello! Send /{more} for an image"),
+    ("help-header", "Commands:"),
+    ("help-alias", ", or /{alias}"),
+    ("help-admin-only", " (admins only in groups)"),
+    ("admin-only", "Only the admins of this chat can use /{command}"),
+    ("cmd-more", "Sends random images"),
+    ("cmd-artist", "Sends images of an artist"),
+    ("cmd-series", "Sends images of a series"),
+    ("cmd-favs", "Sends or lists your favorites"),
+    ("cmd-subscribe", "Sends an image every day"),
+    ("cmd-unsubscribe", "Stops the daily images"),
+    ("cmd-count", "Counts the matching images"),
+    ("cmd-top", "Sends one of the best voted images"),
+    ("cmd-settings", "Shows or changes the settings of the chat"),
+    ("cmd-help", "Lists the commands"),
+    ("cmd-start", "Says hello"),
+    ("cmd-version", "Prints the version of the bot"),
+    ("button-another", "🔁 another"),
+    ("button-source", "source"),
+    (
+        "favs-none",
+        "You have no favorites yet, press ⭐ under an image to save it",
+    ),
+    (
+        "favs-gone",
+        "None of your favorites is in the database anymore, \"{title}\" was {link}",
+    ),
+    (
+        "favs-private",
+        "Ask me for your list of favorites in a private chat",
+    ),
+    ("favs-no-page", "There are {count} favorites, no page {page}"),
+    ("favs-no-page.one", "There is {count} favorite, no page {page}"),
+    ("favs-page", "Your favorites, page {page}/{pages}:"),
+    ("favs-dead", "(not in the database anymore)"),
+    ("favs-next", "/{command} list {page} for the next page"),
+    (
+        "subscription",
+        "This chat gets an image every day at {time} ({timezone}){query}",
+    ),
+    ("subscription-query", " for \"{query}\""),
+    ("subscribe-bad-time", "'{time}' isn't a time, write it like 08:30"),
+    (
+        "subscribed",
+        "I will send an image every day at {time} ({timezone}){query}. Use /{settings} timezone to change the timezone",
+    ),
+    ("unsubscribed", "This chat won't get daily images anymore"),
+    ("not-subscribed", "This chat isn't subscribed to daily images"),
+    ("search-unclosed-quote", "A quote is never closed in your search"),
+    (
+        "search-empty-term",
+        "'-' and '*' must be attached to a word, like -sad or neko*",
+    ),
+    (
+        "search-misplaced-or",
+        "OR must be placed between two words, like maid OR nurse",
+    ),
+    (
+        "search-only-exclusions",
+        "A search can't only exclude words, add at least one word to look for",
+    ),
+    (
+        "search-excluded-filter",
+        "Filters like sub: or type: can't be excluded with '-'",
+    ),
+    (
+        "search-missing-value",
+        "'{filter}:' needs a value, like sub:wholesomeyuri or type:png",
+    ),
+    (
+        "search-invalid-date",
+        "'{value}' isn't a date, write it like 2023, 2023-06 or 2023-06-15",
+    ),
+    (
+        "search-invalid-score",
+        "'{value}' isn't a score, write it like score:>500 or score:<10",
+    ),
+    (
+        "search-unknown-type",
+        "'{value}' isn't a media type, use jpg, png or gif",
+    ),
+    (
+        "search-unknown-orientation",
+        "'{value}' isn't an orientation, use portrait or landscape",
+    ),
+    (
+        "search-invalid-resolution",
+        "'{value}' isn't a resolution, write it in pixels like res:1000",
+    ),
+];
+
+const FR: &[(&str, &str)] = &[
+    ("language", "français"),
+    (
+        "no-image",
+        "Il n'y a aucune image pour ça dans la base. Désolé :(",
+    ),
+    (
+        "no-image-suggestion",
+        "Il n'y a aucune image pour ça dans la base. Vouliez-vous dire « {suggestion} » ?",
+    ),
+    ("usage", "Utilisation : {usage}"),
+    ("vote-thanks", "Merci pour votre vote !"),
+    ("favorite-added", "Ajoutée à vos favoris ⭐"),
+    ("favorite-removed", "Retirée de vos favoris"),
+    ("favorite-gone", "Cette image n'est plus dans la base"),
+    (
+        "top-none",
+        "Aucune image n'a encore reçu de vote sur cette période",
+    ),
+    ("count", "Il y a {count} liens dans la base pour cette recherche"),
+    (
+        "count.one",
+        "Il y a {count} lien dans la base pour cette recherche",
+    ),
+    (
+        "settings-current",
+        "Réglages actuels :\nmode : {mode}\nalbum : {album}\nfuseau horaire : {timezone}\nlangue : {language}\ncommandes par minute : {limit}\nrésolution minimale : {resolution}",
+    ),
+    ("settings-language-auto", "auto, la langue de chaque utilisateur"),
+    ("settings-mode-set", "Mode de sélection réglé sur {mode}"),
+    (
+        "settings-album-set",
+        "Les albums sont maintenant limités à {count} images",
+    ),
+    (
+        "settings-album-set.one",
+        "Les albums sont maintenant limités à {count} image",
+    ),
+    ("settings-timezone-set", "Fuseau horaire réglé sur {timezone}"),
+    ("settings-language-set", "Je répondrai maintenant en français"),
+    (
+        "settings-resolution-set",
+        "Les images plus petites que {resolution}×{resolution} pixels ne seront plus envoyées",
+    ),
+    ("settings-resolution-any", "toutes"),
+    (
+        "settings-resolution-any-set",
+        "Les images de toutes tailles seront maintenant envoyées",
+    ),
+    (
+        "settings-language-auto-set",
+        "Je répondrai maintenant dans la langue de chaque utilisateur",
+    ),
+    (
+        "settings-usage",
+        "Utilisation :\n/{command} mode <{modes}>\n/{command} album <1-{max}>\n/{command} timezone <décalage UTC, comme +02:00>\n/{command} language <{languages}|auto>\n/{command} limit <1-{max_limit}|default>\n/{command} resolution <pixels|any>",
+    ),
+    (
+        "settings-limit-set",
+        "Ce chat peut maintenant envoyer {count} commandes par minute",
+    ),
+    (
+        "settings-limit-set.one",
+        "Ce chat peut maintenant envoyer {count} commande par minute",
+    ),
+    (
+        "settings-limit-default",
+        "Ce chat a maintenant la limite par défaut de {count} commandes par minute",
+    ),
+    (
+        "settings-limit-default.one",
+        "Ce chat a maintenant la limite par défaut de {count} commande par minute",
+    ),
+    (
+        "cooldown",
+        "Trop de commandes ont été envoyées, attendez une minute avant la prochaine",
+    ),
+    ("unrecognized", "Commande inconnue, voir /{help}"),
+    ("start", "Bonjour ! Envoyez /{more} pour recevoir une image"),
+    ("help-header", "Commandes :"),
+    ("help-alias", ", ou /{alias}"),
+    (
+        "help-admin-only",
+        " (administrateurs seulement dans les groupes)",
+    ),
+    (
+        "admin-only",
+        "Seuls les administrateurs de ce chat peuvent utiliser /{command}",
+    ),
+    ("cmd-more", "Envoie des images au hasard"),
+    ("cmd-artist", "Envoie des images d'un artiste"),
+    ("cmd-series", "Envoie des images d'une série"),
+    ("cmd-favs", "Envoie ou liste vos favoris"),
+    ("cmd-subscribe", "Envoie une image tous les jours"),
+    ("cmd-unsubscribe", "Arrête les images quotidiennes"),
+    ("cmd-count", "Compte les images correspondantes"),
+    ("cmd-top", "Envoie une des images les mieux notées"),
+    ("cmd-settings", "Affiche ou change les réglages du chat"),
+    ("cmd-help", "Liste les commandes"),
+    ("cmd-start", "Dit bonjour"),
+    ("cmd-version", "Affiche la version du bot"),
+    ("button-another", "🔁 une autre"),
+    ("button-source", "source"),
+    (
+        "favs-none",
+        "Vous n'avez pas encore de favoris, appuyez sur ⭐ sous une image pour l'enregistrer",
+    ),
+    (
+        "favs-gone",
+        "Aucun de vos favoris n'est plus dans la base, « {title} » était {link}",
+    ),
+    (
+        "favs-private",
+        "Demandez-moi la liste de vos favoris en message privé",
+    ),
+    ("favs-no-page", "Il y a {count} favoris, pas de page {page}"),
+    ("favs-no-page.one", "Il y a {count} favori, pas de page {page}"),
+    ("favs-page", "Vos favoris, page {page}/{pages} :"),
+    ("favs-dead", "(plus dans la base)"),
+    ("favs-next", "/{command} list {page} pour la page suivante"),
+    (
+        "subscription",
+        "Ce chat reçoit une image tous les jours à {time} ({timezone}){query}",
+    ),
+    ("subscription-query", " pour « {query} »"),
+    (
+        "subscribe-bad-time",
+        "'{time}' n'est pas une heure, écrivez-la comme 08:30",
+    ),
+    (
+        "subscribed",
+        "J'enverrai une image tous les jours à {time} ({timezone}){query}. Utilisez /{settings} timezone pour changer de fuseau horaire",
+    ),
+    ("unsubscribed", "Ce chat ne recevra plus d'images quotidiennes"),
+    (
+        "not-subscribed",
+        "Ce chat n'est pas abonné aux images quotidiennes",
+    ),
+    (
+        "search-unclosed-quote",
+        "Un guillemet n'est jamais fermé dans votre recherche",
+    ),
+    (
+        "search-empty-term",
+        "'-' et '*' doivent être collés à un mot, comme -sad ou neko*",
+    ),
+    (
+        "search-misplaced-or",
+        "OR doit être placé entre deux mots, comme maid OR nurse",
+    ),
+    (
+        "search-only-exclusions",
+        "Une recherche ne peut pas seulement exclure des mots, ajoutez au moins un mot à chercher",
+    ),
+    (
+        "search-excluded-filter",
+        "Les filtres comme sub: ou type: ne peuvent pas être exclus avec '-'",
+    ),
+    (
+        "search-missing-value",
+        "'{filter}:' a besoin d'une valeur, comme sub:wholesomeyuri ou type:png",
+    ),
+    (
+        "search-invalid-date",
+        "'{value}' n'est pas une date, écrivez-la comme 2023, 2023-06 ou 2023-06-15",
+    ),
+    (
+        "search-invalid-score",
+        "'{value}' n'est pas un score, écrivez-le comme score:>500 ou score:<10",
+    ),
+    (
+        "search-unknown-type",
+        "'{value}' n'est pas un type de média, utilisez jpg, png ou gif",
+    ),
+    (
+        "search-unknown-orientation",
+        "'{value}' n'est pas une orientation, utilisez portrait ou landscape",
+    ),
+    (
+        "search-invalid-resolution",
+        "'{value}' n'est pas une résolution, écrivez-la en pixels comme res:1000",
+    ),
+];
+
+/// Names of the `{placeholders}` of a message
+#[cfg(test)]
+fn placeholders(message: &str) -> Vec<&str> {
+    let mut names: Vec<&str> = message
+        .split('{')
+        .skip(1)
+        .filter_map(|part| part.split('}').next())
+        .collect();
+    names.sort_unstable();
+    names
+}
+
+#[test]
+fn test_catalogs_match() {
+    for lang in Lang::ALL.iter() {
+        for (key, message) in EN {
+            let translated = lookup(*lang, key).unwrap_or_else(|| panic!("{:?} {}", lang, key));
+            assert_eq!(placeholders(message), placeholders(translated), "{}", key);
+        }
+        for (key, _) in lang.catalog() {
+            assert!(lookup(Lang::En, key).is_some(), "{}", key);
+        }
+    }
+}
+
+#[test]
+fn test_tr() {
+    assert_eq!(Lang::Fr, Lang::resolve(None, Some("fr-CA")));
+    assert_eq!(Lang::En, Lang::resolve(Some("en"), Some("fr")));
+    assert_eq!(Lang::En, Lang::resolve(None, Some("de")));
+    assert_eq!(
+        "Unrecognized command, see /aide",
+        tr(Lang::En, "unrecognized", &[("help", &"aide")])
+    );
+    assert_eq!(
+        "This chat gets an image every day at 08:30 (UTC+00:00) for \"{time}\"",
+        tr(
+            Lang::En,
+            "subscription",
+            &[
+                ("time", &"08:30"),
+                ("timezone", &"UTC+00:00"),
+                ("query", &" for \"{time}\""),
+            ]
+        )
+    );
+    assert_eq!(
+        "There is 1 link in the database for this query",
+        tr_count(Lang::En, "count", 1, &[])
+    );
+    assert_eq!(
+        "Il y a 0 lien dans la base pour cette recherche",
+        tr_count(Lang::Fr, "count", 0, &[])
+    );
+    assert_eq!(
+        "Il y a 2 favoris, pas de page 3",
+        tr_count(Lang::Fr, "favs-no-page", 2, &[("page", &3)])
+    );
+}
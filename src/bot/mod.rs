@@ -0,0 +1,1207 @@
+mod channel;
+mod commands;
+mod favorites;
+mod inline;
+mod keyboard;
+mod locale;
+mod outbox;
+mod photo;
+mod requests;
+mod schedule;
+mod throttle;
+mod webhook;
+
+pub use channel::{run_channel_poster, ChannelConfig};
+pub use commands::{CommandNames, Registry};
+pub use outbox::Outbox;
+pub use schedule::run_scheduler;
+pub use webhook::WebhookConfig;
+
+use crate::db;
+use crate::rate_limit::RateLimiter;
+use crate::search::{self, Search};
+use crate::selection::{self, SelectionMode};
+use crate::utils::unix_now;
+use crate::{Result, YuribotError};
+use commands::{Args, CommandKind, Permission};
+use locale::{tr, tr_count, Lang};
+use throttle::{Throttle, Verdict};
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::StreamExt;
+use rand::seq::SliceRandom;
+use telegram_bot::{
+    prelude::{CanAnswerCallbackQuery, CanReplySendMessage},
+    types::{
+        CallbackQuery, ChatId, GetMe, Message, MessageChat, MessageOrChannelPost, Update,
+        UpdateKind, User,
+    },
+    Api, CanDeleteMessage,
+};
+use tokio::sync::mpsc;
+
+mod message {
+    use telegram_bot::types::{Message, MessageChat, MessageKind};
+    pub type ArgRange = std::ops::Range<usize>;
+    pub fn get_arg(message: &Message, range: ArgRange) -> Option<&str> {
+        let data = match &message.kind {
+            MessageKind::Text { ref data, .. } => data,
+            _ => return None,
+        };
+        Some(data[range].trim())
+    }
+    pub fn split_count(arg: &str) -> (Option<usize>, &str) {
+        let (first, rest) = match arg.find(char::is_whitespace) {
+            Some(i) => (&arg[..i], arg[i..].trim()),
+            None => (arg, ""),
+        };
+        match first.parse() {
+            Ok(count) if count > 0 => (Some(count), rest),
+            _ => (None, arg),
+        }
+    }
+    pub fn is_private(message: &Message) -> bool {
+        if let MessageChat::Private(_) = message.chat {
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Telegram doesn't allow more than 10 pictures in an album
+const MAX_ALBUM_SIZE: i32 = 10;
+
+/// Highest minimum resolution a chat can set, hardly any image is larger
+const MAX_MIN_RESOLUTION: i32 = 5000;
+
+/// Picks up to `count` links, capped by the chat's maximum album size
+async fn pick_links(
+    database: &db::DbPool,
+    weights: &selection::Weights,
+    chat_id: ChatId,
+    search: &Search,
+    count: usize,
+) -> Result<Vec<db::model::Link>> {
+    let database = database.get().await?;
+    let settings = database.get_chat_settings(chat_id.into())?;
+    let mode = settings
+        .selection_mode
+        .parse()
+        .unwrap_or(SelectionMode::Uniform);
+    let count = count.min(settings.max_album_size.max(1) as usize);
+    let search = search.with_min_resolution(settings.min_resolution);
+    Ok(selection::pick_links(
+        &database, weights, mode, &search, count,
+    )?)
+}
+
+/// Language of the replies in the chat, the one chosen in its settings or else the user's
+async fn chat_lang(database: &db::DbPool, chat_id: ChatId, user: Option<&User>) -> Result<Lang> {
+    let settings = database.get().await?.get_chat_settings(chat_id.into())?;
+    Ok(Lang::resolve(
+        settings.language.as_deref(),
+        user.and_then(|user| user.language_code.as_deref()),
+    ))
+}
+
+/// Sends the link with its keyboard, `user` being who asked for it if anyone did
+async fn send_link(
+    database: &db::DbPool,
+    outbox: &Outbox,
+    chat: ChatId,
+    user: Option<&User>,
+    link: db::model::Link,
+    query: &str,
+    lang: Lang,
+) -> Result<()> {
+    info!(
+        "Sending image\n\t{}: {}\n\tUser: {:?}\n\tChat: {:?}",
+        link.title,
+        link.link,
+        user.map(|user| &user.username),
+        chat
+    );
+    let keyboard = keyboard::link_keyboard(&link, query, lang);
+    photo::send_photo(database, outbox, chat, &link, &link.title, Some(keyboard)).await
+}
+
+/// Number of links tried when their images can't be sent
+const MAX_LINK_ATTEMPTS: usize = 3;
+
+/// Like `send_link`, but sends other random links matching the query when the image of the
+/// link can't be sent
+async fn send_link_or_another(
+    database: &db::DbPool,
+    outbox: &Outbox,
+    chat: ChatId,
+    user: Option<&User>,
+    mut link: db::model::Link,
+    query: &str,
+    lang: Lang,
+) -> Result<()> {
+    let min_resolution = database
+        .get()
+        .await?
+        .get_chat_settings(chat.into())?
+        .min_resolution;
+    let search = match search::parse(query) {
+        Ok(search) => search.with_min_resolution(min_resolution),
+        // Another image is only picked with the query of the first one
+        Err(e) => {
+            warn!("not trying another image for {:?}: {}", query, e);
+            return send_link(database, outbox, chat, user, link, query, lang).await;
+        }
+    };
+    let mut tried = Vec::with_capacity(MAX_LINK_ATTEMPTS);
+    for _ in 1..MAX_LINK_ATTEMPTS {
+        tried.push(link.id);
+        match send_link(database, outbox, chat, user, link, query, lang).await {
+            Err(YuribotError::UnavailableImageError(link_id)) => {
+                warn!(
+                    "the image of link {} can't be sent, trying another",
+                    link_id
+                );
+            }
+            result => return result,
+        }
+        let candidates = database
+            .get()
+            .await?
+            .fetch_random_links(&search, MAX_LINK_ATTEMPTS as i64)?;
+        link = match candidates
+            .into_iter()
+            .find(|candidate| !tried.contains(&candidate.id))
+        {
+            Some(link) => link,
+            None => return Ok(()),
+        };
+    }
+    send_link(database, outbox, chat, user, link, query, lang).await
+}
+
+async fn send_album(
+    database: &db::DbPool,
+    outbox: &Outbox,
+    chat: &MessageChat,
+    user: &User,
+    links: Vec<db::model::Link>,
+    query: &str,
+    lang: Lang,
+) -> Result<()> {
+    info!(
+        "Sending album of {} images\n\tUser: {:?}\n\tChat: {:?}",
+        links.len(),
+        user.username,
+        chat
+    );
+    let media = links
+        .iter()
+        .map(|link| {
+            debug!("album image {}: {}", link.title, link.link);
+            let media = link.file_id.as_ref().unwrap_or(&link.link);
+            requests::InputMediaPhoto::new(media.clone(), link.title.clone())
+        })
+        .collect();
+    let response = outbox
+        .send_timeout(
+            Some(chat.id()),
+            requests::SendMediaGroup::new(chat.id(), media),
+            Duration::from_secs(10),
+        )
+        .await;
+    match response {
+        Err(e) if photo::is_fetch_error(&e.to_string()) => {
+            // Telegram doesn't tell which image it couldn't fetch
+            warn!(
+                "Telegram couldn't fetch the album, sending its images: {}",
+                e
+            );
+            for link in links {
+                send_link_or_another(database, outbox, chat.id(), Some(user), link, query, lang)
+                    .await?;
+            }
+        }
+        response => {
+            let response = response?;
+            debug!("responded with: {:?}", response);
+            // The messages of the album are in the order of its images
+            let file_ids: Vec<Option<&str>> = response
+                .iter()
+                .flatten()
+                .map(photo::photo_file_id)
+                .collect();
+            for (i, link) in links.iter().enumerate() {
+                let file_id = file_ids.get(i).copied().flatten();
+                photo::record_sent(database, link, file_id).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parses the search of a command, replying with what is wrong with it if it is malformed
+async fn parse_search(
+    outbox: &Outbox,
+    message: &Message,
+    arg: &str,
+    lang: Lang,
+) -> Result<Option<Search>> {
+    match search::parse(arg) {
+        Ok(search) => Ok(Some(search)),
+        Err(e) => {
+            outbox
+                .send_timeout(
+                    Some(message.chat.id()),
+                    message.text_reply(locale::search_error(lang, &e)),
+                    Duration::from_secs(5),
+                )
+                .await?;
+            Ok(None)
+        }
+    }
+}
+
+async fn handle_more(
+    database: db::DbPool,
+    outbox: Outbox,
+    weights: selection::Weights,
+    message: Message,
+    args: Args,
+) -> Result<()> {
+    let lang = chat_lang(&database, message.chat.id(), Some(&message.from)).await?;
+    let search = args.get("search").unwrap_or_default();
+    send_search(
+        database,
+        outbox,
+        weights,
+        &message,
+        args.count(),
+        search,
+        lang,
+    )
+    .await
+}
+
+/// Handles /artist and /series, which are searches on the tag extracted from the titles
+async fn handle_tag(
+    database: db::DbPool,
+    outbox: Outbox,
+    weights: selection::Weights,
+    message: Message,
+    args: Args,
+    tag: &'static str,
+) -> Result<()> {
+    let lang = chat_lang(&database, message.chat.id(), Some(&message.from)).await?;
+    let name = args.get("name").unwrap_or_default();
+    let query = format!("{}:\"{}\"", tag, name.replace('"', ""));
+    send_search(
+        database,
+        outbox,
+        weights,
+        &message,
+        args.count(),
+        &query,
+        lang,
+    )
+    .await
+}
+
+/// Sends `count` images, or a single one, matching the search in reply to the message
+async fn send_search(
+    database: db::DbPool,
+    outbox: Outbox,
+    weights: selection::Weights,
+    message: &Message,
+    count: Option<usize>,
+    arg: &str,
+    lang: Lang,
+) -> Result<()> {
+    let search = match parse_search(&outbox, message, arg, lang).await? {
+        Some(search) => search,
+        None => return Ok(()),
+    };
+    let mut links = pick_links(
+        &database,
+        &weights,
+        message.chat.id(),
+        &search,
+        count.unwrap_or(1),
+    )
+    .await?;
+    match links.len() {
+        0 => {
+            let vocabulary = database.get().await?.vocabulary()?;
+            if let Some(suggestion) = search::suggest(arg, &vocabulary) {
+                let mut reply = message.text_reply(tr(
+                    lang,
+                    "no-image-suggestion",
+                    &[("suggestion", &suggestion)],
+                ));
+                if let Some(keyboard) = keyboard::suggestion_keyboard(&suggestion) {
+                    reply.reply_markup(keyboard);
+                }
+                outbox
+                    .send_timeout(Some(message.chat.id()), reply, Duration::from_secs(5))
+                    .await?;
+                return Ok(());
+            }
+            let res = outbox
+                .send_timeout(
+                    Some(message.chat.id()),
+                    message.text_reply(tr(lang, "no-image", &[])),
+                    Duration::from_secs(5),
+                )
+                .await?;
+            let Some(res) = res else {
+                return Ok(());
+            };
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            outbox
+                .send_timeout(None, res.delete(), Duration::from_secs(5))
+                .await?;
+            Ok(())
+        }
+        1 => {
+            send_link_or_another(
+                &database,
+                &outbox,
+                message.chat.id(),
+                Some(&message.from),
+                links.remove(0),
+                arg,
+                lang,
+            )
+            .await
+        }
+        _ => {
+            send_album(
+                &database,
+                &outbox,
+                &message.chat,
+                &message.from,
+                links,
+                arg,
+                lang,
+            )
+            .await
+        }
+    }
+}
+
+async fn handle_callback(
+    database: db::DbPool,
+    outbox: Outbox,
+    weights: selection::Weights,
+    throttle: Arc<Throttle>,
+    callback: CallbackQuery,
+) -> Result<()> {
+    let action = callback
+        .data
+        .as_ref()
+        .and_then(|data| keyboard::Action::from_data(data));
+    let chat = match &callback.message {
+        Some(MessageOrChannelPost::Message(message)) => Some(&message.chat),
+        _ => None,
+    };
+    let lang = match chat {
+        Some(chat) => chat_lang(&database, chat.id(), Some(&callback.from)).await?,
+        None => Lang::resolve(None, callback.from.language_code.as_deref()),
+    };
+    match (action, chat) {
+        (Some(keyboard::Action::More { .. }), Some(chat))
+            if throttle.check(callback.from.id.into(), chat.id().into()) != Verdict::Allowed =>
+        {
+            outbox
+                .send_timeout(
+                    None,
+                    callback.answer(tr(lang, "cooldown", &[])),
+                    Duration::from_secs(5),
+                )
+                .await?;
+        }
+        (Some(keyboard::Action::More { query }), Some(chat)) => {
+            let search = match search::parse(&query) {
+                Ok(search) => search,
+                Err(e) => {
+                    outbox
+                        .send_timeout(
+                            None,
+                            callback.answer(locale::search_error(lang, &e)),
+                            Duration::from_secs(5),
+                        )
+                        .await?;
+                    return Ok(());
+                }
+            };
+            match pick_links(&database, &weights, chat.id(), &search, 1)
+                .await?
+                .pop()
+            {
+                Some(link) => {
+                    outbox
+                        .send_timeout(None, callback.acknowledge(), Duration::from_secs(5))
+                        .await?;
+                    send_link_or_another(
+                        &database,
+                        &outbox,
+                        chat.id(),
+                        Some(&callback.from),
+                        link,
+                        &query,
+                        lang,
+                    )
+                    .await?;
+                }
+                None => {
+                    outbox
+                        .send_timeout(
+                            None,
+                            callback.answer(tr(lang, "no-image", &[])),
+                            Duration::from_secs(5),
+                        )
+                        .await?;
+                }
+            }
+        }
+        (Some(keyboard::Action::Vote { link_id, up }), _) => {
+            info!(
+                "Vote {} on link {} by {:?}",
+                if up { "up" } else { "down" },
+                link_id,
+                callback.from.username
+            );
+            database.get().await?.vote(
+                link_id,
+                callback.from.id.into(),
+                if up { 1 } else { -1 },
+                unix_now(),
+            )?;
+            outbox
+                .send_timeout(
+                    None,
+                    callback.answer(tr(lang, "vote-thanks", &[])),
+                    Duration::from_secs(5),
+                )
+                .await?;
+        }
+        (Some(keyboard::Action::Favorite { link_id }), _) => {
+            let answer = match database.get().await?.toggle_favorite(
+                callback.from.id.into(),
+                link_id,
+                unix_now(),
+            )? {
+                Some(true) => "favorite-added",
+                Some(false) => "favorite-removed",
+                None => "favorite-gone",
+            };
+            outbox
+                .send_timeout(
+                    None,
+                    callback.answer(tr(lang, answer, &[])),
+                    Duration::from_secs(5),
+                )
+                .await?;
+        }
+        _ => {
+            outbox
+                .send_timeout(None, callback.acknowledge(), Duration::from_secs(5))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Number of best voted links /top picks from
+const TOP_LINKS: i64 = 10;
+
+/// Timestamp from which the votes are counted for the /top period
+fn top_since(period: Option<&str>) -> i64 {
+    let days = match period {
+        Some("week") => 7,
+        Some("month") => 30,
+        _ => return 0,
+    };
+    unix_now() - days * 24 * 60 * 60
+}
+
+async fn handle_top(
+    database: db::DbPool,
+    outbox: Outbox,
+    message: Message,
+    args: Args,
+) -> Result<()> {
+    let query = args.get("search").unwrap_or_default();
+    let since = top_since(args.get("period"));
+    let lang = chat_lang(&database, message.chat.id(), Some(&message.from)).await?;
+    let search = match parse_search(&outbox, &message, query, lang).await? {
+        Some(search) => search,
+        None => return Ok(()),
+    };
+    let link = {
+        let database = database.get().await?;
+        let settings = database.get_chat_settings(message.chat.id().into())?;
+        let search = search.with_min_resolution(settings.min_resolution);
+        let top = database.top_links(&search, since, TOP_LINKS)?;
+        match top.choose(&mut rand::thread_rng()) {
+            Some(link_id) => Some(database.get_link(*link_id)?),
+            None => None,
+        }
+    };
+    match link {
+        Some(link) => {
+            send_link(
+                &database,
+                &outbox,
+                message.chat.id(),
+                Some(&message.from),
+                link,
+                query,
+                lang,
+            )
+            .await
+        }
+        None => {
+            outbox
+                .send_timeout(
+                    Some(message.chat.id()),
+                    message.text_reply(tr(lang, "top-none", &[])),
+                    Duration::from_secs(5),
+                )
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+async fn handle_count(
+    database: db::DbPool,
+    outbox: Outbox,
+    message: Message,
+    args: Args,
+) -> Result<()> {
+    let lang = chat_lang(&database, message.chat.id(), Some(&message.from)).await?;
+    let arg = args.get("search").unwrap_or_default();
+    let search = match parse_search(&outbox, &message, arg, lang).await? {
+        Some(search) => search,
+        None => return Ok(()),
+    };
+    let link_count = database.get().await?.count_links(&search)?;
+    outbox
+        .send_timeout(
+            Some(message.chat.id()),
+            message.text_reply(tr_count(lang, "count", link_count, &[])),
+            Duration::from_secs(5),
+        )
+        .await?;
+    Ok(())
+}
+
+async fn handle_settings(
+    database: db::DbPool,
+    outbox: Outbox,
+    message: Message,
+    args: Args,
+    commands: Arc<Registry>,
+    throttle: Arc<Throttle>,
+) -> Result<()> {
+    let chat_id = message.chat.id().into();
+    let name = &commands.get(CommandKind::Settings).name;
+    let settings = database.get().await?.get_chat_settings(chat_id)?;
+    let user_language = message.from.language_code.as_deref();
+    let lang = Lang::resolve(settings.language.as_deref(), user_language);
+    let reply = match (args.get("setting"), args.get("value")) {
+        (None, _) => {
+            let language = match &settings.language {
+                Some(language) => language.clone(),
+                None => tr(lang, "settings-language-auto", &[]),
+            };
+            let resolution = match settings.min_resolution {
+                Some(min_resolution) => min_resolution.to_string(),
+                None => tr(lang, "settings-resolution-any", &[]),
+            };
+            tr(
+                lang,
+                "settings-current",
+                &[
+                    ("mode", &settings.selection_mode),
+                    ("album", &settings.max_album_size),
+                    (
+                        "timezone",
+                        &schedule::format_utc_offset(settings.utc_offset),
+                    ),
+                    ("language", &language),
+                    ("limit", &throttle.chat_limit(chat_id)),
+                    ("resolution", &resolution),
+                ],
+            )
+        }
+        (Some("mode"), Some(mode)) => match mode.parse::<SelectionMode>() {
+            Ok(mode) => {
+                database
+                    .get()
+                    .await?
+                    .set_selection_mode(chat_id, mode.as_str())?;
+                tr(lang, "settings-mode-set", &[("mode", &mode.as_str())])
+            }
+            Err(()) => settings_usage(name, lang),
+        },
+        (Some("album"), Some(size)) => match size.parse::<i32>() {
+            Ok(size) if (1..=MAX_ALBUM_SIZE).contains(&size) => {
+                database.get().await?.set_max_album_size(chat_id, size)?;
+                tr_count(lang, "settings-album-set", size.into(), &[])
+            }
+            _ => settings_usage(name, lang),
+        },
+        (Some("timezone"), Some(offset)) => match schedule::parse_utc_offset(offset) {
+            Some(utc_offset) => {
+                let database = database.get().await?;
+                database.set_utc_offset(chat_id, utc_offset)?;
+                schedule::reschedule(&database, chat_id, utc_offset)?;
+                let timezone = schedule::format_utc_offset(utc_offset);
+                tr(lang, "settings-timezone-set", &[("timezone", &timezone)])
+            }
+            None => settings_usage(name, lang),
+        },
+        (Some("language"), Some("auto")) => {
+            database.get().await?.set_language(chat_id, None)?;
+            let lang = Lang::resolve(None, user_language);
+            tr(lang, "settings-language-auto-set", &[])
+        }
+        (Some("language"), Some(code)) => match Lang::from_code(code) {
+            Some(lang) => {
+                database
+                    .get()
+                    .await?
+                    .set_language(chat_id, Some(lang.as_str()))?;
+                tr(lang, "settings-language-set", &[])
+            }
+            None => settings_usage(name, lang),
+        },
+        (Some("limit"), Some("default")) => {
+            database
+                .get()
+                .await?
+                .set_commands_per_minute(chat_id, None)?;
+            throttle.set_chat_limit(chat_id, None);
+            let limit = throttle.chat_limit(chat_id);
+            tr_count(lang, "settings-limit-default", limit.into(), &[])
+        }
+        (Some("limit"), Some(limit)) => match limit.parse::<i32>() {
+            Ok(limit) if (1..=throttle::MAX_COMMANDS_PER_MINUTE).contains(&limit) => {
+                database
+                    .get()
+                    .await?
+                    .set_commands_per_minute(chat_id, Some(limit))?;
+                throttle.set_chat_limit(chat_id, Some(limit as u32));
+                tr_count(lang, "settings-limit-set", limit.into(), &[])
+            }
+            _ => settings_usage(name, lang),
+        },
+        (Some("resolution"), Some("any")) => {
+            database.get().await?.set_min_resolution(chat_id, None)?;
+            tr(lang, "settings-resolution-any-set", &[])
+        }
+        (Some("resolution"), Some(resolution)) => match resolution.parse::<i32>() {
+            Ok(resolution) if (1..=MAX_MIN_RESOLUTION).contains(&resolution) => {
+                database
+                    .get()
+                    .await?
+                    .set_min_resolution(chat_id, Some(resolution))?;
+                tr(
+                    lang,
+                    "settings-resolution-set",
+                    &[("resolution", &resolution)],
+                )
+            }
+            _ => settings_usage(name, lang),
+        },
+        _ => settings_usage(name, lang),
+    };
+    outbox
+        .send_timeout(
+            Some(message.chat.id()),
+            message.text_reply(reply),
+            Duration::from_secs(5),
+        )
+        .await?;
+    Ok(())
+}
+
+fn settings_usage(name: &str, lang: Lang) -> String {
+    let modes: Vec<&str> = SelectionMode::ALL.iter().map(|m| m.as_str()).collect();
+    let languages: Vec<&str> = Lang::ALL.iter().map(|lang| lang.as_str()).collect();
+    tr(
+        lang,
+        "settings-usage",
+        &[
+            ("command", &name),
+            ("modes", &modes.join("|")),
+            ("max", &MAX_ALBUM_SIZE),
+            ("languages", &languages.join("|")),
+            ("max_limit", &throttle::MAX_COMMANDS_PER_MINUTE),
+        ],
+    )
+}
+
+async fn handle_unrecognized(
+    is_directed_to_bot: bool,
+    database: db::DbPool,
+    outbox: Outbox,
+    message: Message,
+    help: String,
+) -> Result<()> {
+    if !is_directed_to_bot {
+        return Ok(());
+    }
+    let lang = chat_lang(&database, message.chat.id(), Some(&message.from)).await?;
+    outbox
+        .send_timeout(
+            Some(message.chat.id()),
+            message.text_reply(tr(lang, "unrecognized", &[("help", &help)])),
+            Duration::from_secs(5),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Handles /help, and /start which also greets the user
+async fn handle_help(
+    database: db::DbPool,
+    outbox: Outbox,
+    message: Message,
+    commands: Arc<Registry>,
+    greet: bool,
+) -> Result<()> {
+    let lang = chat_lang(&database, message.chat.id(), Some(&message.from)).await?;
+    let mut text = commands.help(lang);
+    if greet {
+        let more = &commands.get(CommandKind::More).name;
+        text = format!("{}\n\n{}", tr(lang, "start", &[("more", more)]), text);
+    }
+    outbox
+        .send_timeout(
+            Some(message.chat.id()),
+            message.text_reply(text),
+            Duration::from_secs(5),
+        )
+        .await?;
+    Ok(())
+}
+
+async fn reply_cooldown(database: db::DbPool, outbox: Outbox, message: Message) -> Result<()> {
+    let lang = chat_lang(&database, message.chat.id(), Some(&message.from)).await?;
+    outbox
+        .send_timeout(
+            Some(message.chat.id()),
+            message.text_reply(tr(lang, "cooldown", &[])),
+            Duration::from_secs(5),
+        )
+        .await?;
+    Ok(())
+}
+
+async fn reply_admin_only(
+    database: db::DbPool,
+    outbox: Outbox,
+    message: Message,
+    name: String,
+) -> Result<()> {
+    let lang = chat_lang(&database, message.chat.id(), Some(&message.from)).await?;
+    outbox
+        .send_timeout(
+            Some(message.chat.id()),
+            message.text_reply(tr(lang, "admin-only", &[("command", &name)])),
+            Duration::from_secs(5),
+        )
+        .await?;
+    Ok(())
+}
+
+async fn reply_usage(
+    database: db::DbPool,
+    outbox: Outbox,
+    message: Message,
+    usage: String,
+) -> Result<()> {
+    let lang = chat_lang(&database, message.chat.id(), Some(&message.from)).await?;
+    outbox
+        .send_timeout(
+            Some(message.chat.id()),
+            message.text_reply(tr(lang, "usage", &[("usage", &usage)])),
+            Duration::from_secs(5),
+        )
+        .await?;
+    Ok(())
+}
+
+async fn handle_version(outbox: Outbox, message: Message) -> Result<()> {
+    outbox
+        .send_timeout(
+            Some(message.chat.id()),
+            message.text_reply(crate::VERSION),
+            Duration::from_secs(5),
+        )
+        .await?;
+    Ok(())
+}
+
+fn spawn_response<T, E>(fut: T)
+where
+    T: std::future::Future<Output = Result<E>> + Send + 'static,
+    E: Send,
+{
+    tokio::spawn(async {
+        if let Err(e) = fut.await {
+            error!("telegram message handling error: {}", e);
+        }
+    });
+}
+
+#[derive(Debug, Clone)]
+pub struct BotConfig {
+    pub weights: selection::Weights,
+    /// how long Telegram may cache the results of an inline query, in seconds
+    pub inline_cache_time: i64,
+    pub inline_queries_per_minute: u32,
+    pub commands_per_user_per_minute: u32,
+    /// commands per minute of the chats that don't set their own limit
+    pub commands_per_chat_per_minute: u32,
+    /// receive the updates with a webhook instead of long polling
+    pub webhook: Option<WebhookConfig>,
+    pub commands: Registry,
+}
+
+/// State shared by the handlers of the updates
+#[derive(Clone)]
+struct Dispatcher {
+    db_pool: db::DbPool,
+    outbox: Outbox,
+    weights: selection::Weights,
+    inline_limiter: Arc<RateLimiter<i64>>,
+    inline_cache_time: i64,
+    botname: String,
+    commands: Arc<Registry>,
+    throttle: Arc<Throttle>,
+}
+
+impl Dispatcher {
+    /// Spawns the handler of the update
+    fn dispatch(&self, update: Update) {
+        debug!("received update: {:?}", update);
+        let message = match update.kind {
+            UpdateKind::Message(message) => message,
+            UpdateKind::CallbackQuery(callback) => {
+                spawn_response(handle_callback(
+                    self.db_pool.clone(),
+                    self.outbox.clone(),
+                    self.weights,
+                    self.throttle.clone(),
+                    callback,
+                ));
+                return;
+            }
+            UpdateKind::InlineQuery(query) => {
+                spawn_response(inline::handle_inline_query(
+                    self.db_pool.clone(),
+                    self.outbox.clone(),
+                    self.inline_limiter.clone(),
+                    self.inline_cache_time,
+                    query,
+                ));
+                return;
+            }
+            _ => return,
+        };
+        let command = match self.commands.parse(&self.botname, &message) {
+            Some(command) => command,
+            None => return,
+        };
+        debug!("extracted command: {:?}", command);
+        let spec = match command.spec {
+            Some(spec) => spec,
+            None => {
+                let is_directed = command.is_directed || message::is_private(&message);
+                let help = self.commands.get(CommandKind::Help).name.clone();
+                spawn_response(handle_unrecognized(
+                    is_directed,
+                    self.db_pool.clone(),
+                    self.outbox.clone(),
+                    message,
+                    help,
+                ));
+                return;
+            }
+        };
+        match self
+            .throttle
+            .check(message.from.id.into(), message.chat.id().into())
+        {
+            Verdict::Allowed => {}
+            Verdict::Notify => {
+                spawn_response(reply_cooldown(
+                    self.db_pool.clone(),
+                    self.outbox.clone(),
+                    message,
+                ));
+                return;
+            }
+            Verdict::Silent => {
+                debug!("throttled /{} from {:?}", spec.name, message.from.username);
+                return;
+            }
+        }
+        let args =
+            match message::get_arg(&message, command.arg).and_then(|arg| spec.parse_args(arg)) {
+                Some(args) => args,
+                None => {
+                    spawn_response(reply_usage(
+                        self.db_pool.clone(),
+                        self.outbox.clone(),
+                        message,
+                        spec.usage(),
+                    ));
+                    return;
+                }
+            };
+        if spec.permission == Permission::Everyone || message::is_private(&message) {
+            self.run_command(spec.kind, args, message);
+            return;
+        }
+        let dispatcher = self.clone();
+        let (kind, permission) = (spec.kind, spec.permission);
+        let name = spec.name.clone();
+        tokio::spawn(async move {
+            match commands::is_allowed(&dispatcher.outbox, &message, permission).await {
+                Ok(true) => dispatcher.run_command(kind, args, message),
+                Ok(false) => spawn_response(reply_admin_only(
+                    dispatcher.db_pool.clone(),
+                    dispatcher.outbox.clone(),
+                    message,
+                    name,
+                )),
+                Err(e) => error!("failed to check the permission of /{}: {}", name, e),
+            }
+        });
+    }
+
+    fn run_command(&self, kind: CommandKind, args: Args, message: Message) {
+        match kind {
+            CommandKind::More => {
+                spawn_response(handle_more(
+                    self.db_pool.clone(),
+                    self.outbox.clone(),
+                    self.weights,
+                    message,
+                    args,
+                ));
+            }
+            CommandKind::Artist => {
+                spawn_response(handle_tag(
+                    self.db_pool.clone(),
+                    self.outbox.clone(),
+                    self.weights,
+                    message,
+                    args,
+                    "artist",
+                ));
+            }
+            CommandKind::Series => {
+                spawn_response(handle_tag(
+                    self.db_pool.clone(),
+                    self.outbox.clone(),
+                    self.weights,
+                    message,
+                    args,
+                    "series",
+                ));
+            }
+            CommandKind::Favs => {
+                spawn_response(favorites::handle_favs(
+                    self.db_pool.clone(),
+                    self.outbox.clone(),
+                    message,
+                    args,
+                    self.commands.clone(),
+                ));
+            }
+            CommandKind::Subscribe => {
+                spawn_response(schedule::handle_subscribe(
+                    self.db_pool.clone(),
+                    self.outbox.clone(),
+                    message,
+                    args,
+                    self.commands.clone(),
+                ));
+            }
+            CommandKind::Unsubscribe => {
+                spawn_response(schedule::handle_unsubscribe(
+                    self.db_pool.clone(),
+                    self.outbox.clone(),
+                    message,
+                ));
+            }
+            CommandKind::Count => {
+                spawn_response(handle_count(
+                    self.db_pool.clone(),
+                    self.outbox.clone(),
+                    message,
+                    args,
+                ));
+            }
+            CommandKind::Top => {
+                spawn_response(handle_top(
+                    self.db_pool.clone(),
+                    self.outbox.clone(),
+                    message,
+                    args,
+                ));
+            }
+            CommandKind::Settings => {
+                spawn_response(handle_settings(
+                    self.db_pool.clone(),
+                    self.outbox.clone(),
+                    message,
+                    args,
+                    self.commands.clone(),
+                    self.throttle.clone(),
+                ));
+            }
+            CommandKind::Version => {
+                spawn_response(handle_version(self.outbox.clone(), message));
+            }
+            CommandKind::Help | CommandKind::Start => {
+                spawn_response(handle_help(
+                    self.db_pool.clone(),
+                    self.outbox.clone(),
+                    message,
+                    self.commands.clone(),
+                    kind == CommandKind::Start,
+                ));
+            }
+        }
+    }
+}
+
+/// Receives the updates with long polling
+async fn poll(api: &Api, dispatcher: &Dispatcher) {
+    // getUpdates fails while a webhook is set, like one left by a crash in webhook mode
+    if let Err(e) = api
+        .send_timeout(requests::DeleteWebhook, Duration::from_secs(5))
+        .await
+    {
+        warn!("failed to delete the webhook: {}", e);
+    }
+    let mut stream = api.stream();
+    stream.error_delay(Duration::from_secs(5));
+    while let Some(update) = stream.next().await {
+        match update {
+            Ok(update) => dispatcher.dispatch(update),
+            Err(e) => debug!("update error: {}", e),
+        }
+    }
+}
+
+pub async fn start_bot(db_pool: db::DbPool, outbox: Outbox, config: BotConfig) {
+    info!("started the bot");
+    let botname = match outbox
+        .send_timeout(None, GetMe, Duration::from_secs(5))
+        .await
+    {
+        Ok(user) => user
+            .and_then(|u| u.username)
+            .map(|u| {
+                let mut prefix = "@".to_owned();
+                prefix.push_str(&u);
+                prefix
+            })
+            .unwrap_or(String::new()),
+        Err(e) => {
+            error!("fatal: couldn't get bot name: {}", e);
+            return;
+        }
+    };
+    info!("bot running as {}", botname);
+    let chat_limits = match db_pool.get().await {
+        Ok(database) => database.chat_command_limits(),
+        Err(e) => Err(e),
+    };
+    let chat_limits = match chat_limits {
+        Ok(chat_limits) => chat_limits
+            .into_iter()
+            .map(|(chat_id, limit)| (chat_id, limit.max(1) as u32))
+            .collect(),
+        Err(e) => {
+            error!("fatal: couldn't get the limits of the chats: {}", e);
+            return;
+        }
+    };
+    let dispatcher = Dispatcher {
+        db_pool,
+        outbox: outbox.clone(),
+        weights: config.weights,
+        inline_limiter: Arc::new(RateLimiter::new(
+            config.inline_queries_per_minute,
+            Duration::from_secs(60),
+        )),
+        inline_cache_time: config.inline_cache_time,
+        botname,
+        commands: Arc::new(config.commands),
+        throttle: Arc::new(Throttle::new(
+            config.commands_per_user_per_minute,
+            config.commands_per_chat_per_minute,
+            chat_limits,
+        )),
+    };
+    for &lang in Lang::ALL.iter() {
+        // The English menu is the default one, for the users of the other languages too
+        let language_code = match lang {
+            Lang::En => None,
+            _ => Some(lang.as_str()),
+        };
+        let menu =
+            requests::SetMyCommands::new(dispatcher.commands.bot_commands(lang), language_code);
+        if let Err(e) = outbox
+            .send_timeout(None, menu, Duration::from_secs(5))
+            .await
+        {
+            warn!("failed to set the command menu in {}: {}", lang.as_str(), e);
+        }
+    }
+    match config.webhook {
+        None => poll(outbox.api(), &dispatcher).await,
+        Some(webhook) => {
+            let (sender, mut receiver) = mpsc::unbounded_channel();
+            let server = webhook::serve(outbox.api().clone(), webhook, sender);
+            let dispatch = async {
+                while let Some(update) = receiver.recv().await {
+                    dispatcher.dispatch(update);
+                }
+            };
+            let (result, ()) = futures::join!(server, dispatch);
+            if let Err(e) = result {
+                error!("fatal: webhook error: {}", e);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_split_count() {
+    assert_eq!((Some(5), "maid"), message::split_count("5 maid"));
+    assert_eq!((Some(3), ""), message::split_count("3"));
+    assert_eq!((None, "maid outfit"), message::split_count("maid outfit"));
+    assert_eq!((None, "0 maid"), message::split_count("0 maid"));
+    assert_eq!((None, ""), message::split_count(""));
+}
+
+#[test]
+fn test_top_since() {
+    assert_eq!(0, top_since(None));
+    assert_eq!(0, top_since(Some("all")));
+    let week = top_since(Some("week"));
+    assert!(week > 0);
+    assert!(top_since(Some("month")) < week);
+}
@@ -0,0 +1,199 @@
+//! Every request to Telegram goes through the outbox, which spaces the messages to stay under
+//! its flood limits, and sends them again after the wait Telegram asks for when they are hit
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use telegram_bot::{
+    types::{ChatId, Request, ResponseType},
+    Api, Error,
+};
+use tokio::time::{self, Instant};
+
+/// Telegram lets a bot send about 30 messages per second
+const GLOBAL_INTERVAL: Duration = Duration::from_millis(35);
+/// and one message per second in a private chat
+const PRIVATE_CHAT_INTERVAL: Duration = Duration::from_secs(1);
+/// and 20 messages per minute in a group
+const GROUP_INTERVAL: Duration = Duration::from_secs(3);
+/// Number of times a request is sent when Telegram keeps asking to wait
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Time slots reserved for the messages
+#[derive(Debug)]
+struct Schedule {
+    /// no request can be sent before, after Telegram asked to wait without naming a chat
+    paused_until: Instant,
+    /// Times the messages are sent at, kept `GLOBAL_INTERVAL` apart
+    message_slots: BTreeSet<Instant>,
+    next_in_chat: HashMap<i64, Instant>,
+}
+
+impl Schedule {
+    fn new(now: Instant) -> Self {
+        Schedule {
+            paused_until: now,
+            message_slots: BTreeSet::new(),
+            next_in_chat: HashMap::new(),
+        }
+    }
+
+    /// Reserves the time a request can be sent at, requests that aren't messages to a chat
+    /// only wait for the pauses
+    fn reserve(&mut self, chat: Option<i64>, now: Instant) -> Instant {
+        let chat_id = match chat {
+            Some(chat_id) => chat_id,
+            None => return self.paused_until.max(now),
+        };
+        self.next_in_chat.retain(|_, next| *next > now);
+        self.message_slots
+            .retain(|slot| *slot + GLOBAL_INTERVAL > now);
+        let mut at = now
+            .max(self.paused_until)
+            .max(self.next_in_chat.get(&chat_id).copied().unwrap_or(now));
+        // The first free slot from then, a message waiting for its chat doesn't delay the
+        // messages to other chats sent before it
+        for &slot in &self.message_slots {
+            if slot >= at + GLOBAL_INTERVAL {
+                break;
+            }
+            if slot + GLOBAL_INTERVAL > at {
+                at = slot + GLOBAL_INTERVAL;
+            }
+        }
+        self.message_slots.insert(at);
+        // Groups, supergroups and channels have negative ids
+        let interval = if chat_id < 0 {
+            GROUP_INTERVAL
+        } else {
+            PRIVATE_CHAT_INTERVAL
+        };
+        self.next_in_chat.insert(chat_id, at + interval);
+        at
+    }
+
+    /// Nothing is sent to the chat, or at all without a chat, before `until`
+    fn pause(&mut self, chat: Option<i64>, until: Instant) {
+        match chat {
+            Some(chat_id) => {
+                let next = self.next_in_chat.entry(chat_id).or_insert(until);
+                *next = (*next).max(until);
+            }
+            None => self.paused_until = self.paused_until.max(until),
+        }
+    }
+}
+
+/// Wait asked by a "Too Many Requests: retry after N" error. telegram_bot doesn't expose the
+/// parameters of the errors, so it is read from the description
+fn retry_after(description: &str) -> Option<Duration> {
+    let seconds = description.split("retry after ").nth(1)?;
+    let seconds = seconds
+        .split(|c: char| !c.is_ascii_digit())
+        .next()?
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+#[derive(Clone)]
+pub struct Outbox {
+    api: Api,
+    schedule: Arc<Mutex<Schedule>>,
+}
+
+impl Outbox {
+    pub fn new(api: Api) -> Self {
+        Outbox {
+            api,
+            schedule: Arc::new(Mutex::new(Schedule::new(Instant::now()))),
+        }
+    }
+
+    /// The API, for what isn't a request like the stream of updates
+    pub fn api(&self) -> &Api {
+        &self.api
+    }
+
+    /// Like `Api::send_timeout`, once the request can be sent. `chat` is the chat a message
+    /// is sent to, `None` for the requests that aren't messages, like callback answers.
+    /// The timeout only counts the time spent sending the request
+    pub async fn send_timeout<Req: Request>(
+        &self,
+        chat: Option<ChatId>,
+        mut request: Req,
+        timeout: Duration,
+    ) -> Result<Option<<Req::Response as ResponseType>::Type>, Error> {
+        let chat = chat.map(i64::from);
+        let mut attempts = 1;
+        loop {
+            let at = self.schedule.lock().unwrap().reserve(chat, Instant::now());
+            time::sleep_until(at).await;
+            let error = match self.api.send_timeout(&mut request, timeout).await {
+                Err(error) => error,
+                response => return response,
+            };
+            match retry_after(&error.to_string()) {
+                Some(wait) if attempts < MAX_ATTEMPTS => {
+                    warn!("flood limit hit in chat {:?}, waiting {:?}", chat, wait);
+                    self.schedule
+                        .lock()
+                        .unwrap()
+                        .pause(chat, Instant::now() + wait);
+                    attempts += 1;
+                }
+                _ => return Err(error),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_schedule() {
+    let start = Instant::now();
+    let mut schedule = Schedule::new(start);
+    assert_eq!(start, schedule.reserve(Some(1), start));
+    // Another chat waits for the global interval, the same chat for the chat's
+    assert_eq!(start + GLOBAL_INTERVAL, schedule.reserve(Some(2), start));
+    assert_eq!(
+        start + PRIVATE_CHAT_INTERVAL,
+        schedule.reserve(Some(1), start)
+    );
+    let group = schedule.reserve(Some(-3), start);
+    assert_eq!(group + GROUP_INTERVAL, schedule.reserve(Some(-3), group));
+    // Requests that aren't messages don't wait for the messages
+    assert_eq!(start, schedule.reserve(None, start));
+
+    let later = start + Duration::from_secs(10);
+    schedule.pause(Some(1), later + Duration::from_secs(5));
+    assert_eq!(later, schedule.reserve(Some(2), later));
+    assert_eq!(
+        later + Duration::from_secs(5),
+        schedule.reserve(Some(1), later)
+    );
+    schedule.pause(None, later + Duration::from_secs(30));
+    assert_eq!(
+        later + Duration::from_secs(30),
+        schedule.reserve(None, later)
+    );
+    assert_eq!(
+        later + Duration::from_secs(30),
+        schedule.reserve(Some(4), later)
+    );
+    // A burst to a group doesn't delay the messages to the other chats
+    let mut schedule = Schedule::new(start);
+    for _ in 0..10 {
+        schedule.reserve(Some(-100), start);
+    }
+    assert_eq!(start + GLOBAL_INTERVAL, schedule.reserve(Some(42), start));
+}
+
+#[test]
+fn test_retry_after() {
+    assert_eq!(
+        Some(Duration::from_secs(17)),
+        retry_after("Too Many Requests: retry after 17")
+    );
+    assert_eq!(None, retry_after("Bad Request: chat not found"));
+}
@@ -0,0 +1,206 @@
+//! Sending of the images as photos, uploaded when Telegram can't fetch them itself, from the
+//! mirror when their host deleted them
+
+use super::outbox::Outbox;
+use crate::db::{self, model::Link};
+use crate::images;
+use crate::utils::unix_now;
+use crate::{Result, YuribotError};
+
+use std::time::Duration;
+
+use telegram_bot::{
+    prelude::CanSendPhoto,
+    types::{
+        ChatId, InlineKeyboardMarkup, InputFile, InputFileRef, InputFileUpload, MessageKind,
+        MessageOrChannelPost,
+    },
+};
+
+/// Descriptions of the errors Telegram answers when it can't fetch or read the image, or
+/// doesn't know its file id
+const FETCH_ERRORS: &[&str] = &[
+    "wrong file identifier/HTTP URL specified",
+    "wrong remote file identifier specified",
+    "failed to get HTTP URL content",
+    "wrong type of the web page content",
+    "IMAGE_PROCESS_FAILED",
+    "PHOTO_INVALID_DIMENSIONS",
+];
+
+pub fn is_fetch_error(description: &str) -> bool {
+    FETCH_ERRORS
+        .iter()
+        .any(|fetch_error| description.contains(fetch_error))
+}
+
+/// Id of the largest size of the photo sent in the message
+pub fn photo_file_id(message: &MessageOrChannelPost) -> Option<&str> {
+    let kind = match message {
+        MessageOrChannelPost::Message(message) => &message.kind,
+        MessageOrChannelPost::ChannelPost(post) => &post.kind,
+    };
+    match kind {
+        MessageKind::Photo { data, .. } => data.last().map(|size| size.file_id.as_str()),
+        _ => None,
+    }
+}
+
+/// Whether the upload failed because of the image, rather than of the network or of a host
+/// failing for a while
+fn is_image_error(error: &YuribotError) -> bool {
+    match error {
+        YuribotError::ImageFetchError(e) => e.is_permanent(),
+        YuribotError::TelegramSendError(e) => is_fetch_error(&e.to_string()),
+        _ => false,
+    }
+}
+
+/// Number of times the image of a link can't be sent before the link is quarantined
+const MAX_SEND_FAILURES: i32 = 3;
+
+/// Sends the image of the link, from its cached file id when it has one, or else from its link,
+/// uploading it when Telegram can't fetch it. Fails with `UnavailableImageError` when it can't be
+/// sent at all, after counting the failure against the link when the image is at fault
+pub async fn send_photo(
+    database: &db::DbPool,
+    outbox: &Outbox,
+    chat: ChatId,
+    link: &Link,
+    caption: &str,
+    keyboard: Option<InlineKeyboardMarkup>,
+) -> Result<()> {
+    if let Some(file_id) = &link.file_id {
+        let photo = InputFileRef::new(file_id.as_str());
+        match send(
+            outbox,
+            chat,
+            photo,
+            caption,
+            keyboard.clone(),
+            Duration::from_secs(5),
+        )
+        .await
+        {
+            Ok(_) => return record_sent(database, link, None).await,
+            Err(e) if is_fetch_error(&e.to_string()) => {
+                warn!(
+                    "the file id of {} is invalid, sending the link: {}",
+                    link.link, e
+                );
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    let photo = InputFileRef::new(link.link.as_str());
+    let sent = match send(
+        outbox,
+        chat,
+        photo,
+        caption,
+        keyboard.clone(),
+        Duration::from_secs(5),
+    )
+    .await
+    {
+        Ok(sent) => sent,
+        Err(e) if is_fetch_error(&e.to_string()) => {
+            warn!("Telegram couldn't fetch {}, uploading it: {}", link.link, e);
+            match upload_photo(database, outbox, chat, link, caption, keyboard).await {
+                Ok(sent) => sent,
+                Err(e) => {
+                    warn!("failed to upload {}: {}", link.link, e);
+                    if is_image_error(&e) {
+                        let quarantined = database.get().await?.record_send_failure(
+                            link.id,
+                            unix_now(),
+                            MAX_SEND_FAILURES,
+                        )?;
+                        if quarantined {
+                            warn!("quarantined link {}: {}", link.id, link.link);
+                        }
+                    }
+                    return Err(YuribotError::UnavailableImageError(link.id));
+                }
+            }
+        }
+        Err(e) => return Err(e.into()),
+    };
+    record_sent(database, link, sent.as_ref().and_then(photo_file_id)).await
+}
+
+/// Remembers the file id of the photo sent for the link, and that its mirrored image was sent,
+/// which keeps it from being evicted. The failures counted against the link are forgotten, only
+/// consecutive ones get it quarantined
+pub async fn record_sent(database: &db::DbPool, link: &Link, file_id: Option<&str>) -> Result<()> {
+    let database = database.get().await?;
+    if let Some(file_id) = file_id {
+        database.set_file_id(link.id, Some(file_id))?;
+    }
+    database.reset_send_failures(link.id)?;
+    database.touch_mirrored_image(link.id, unix_now())?;
+    Ok(())
+}
+
+async fn send<F: Into<InputFile>>(
+    outbox: &Outbox,
+    chat: ChatId,
+    photo: F,
+    caption: &str,
+    keyboard: Option<InlineKeyboardMarkup>,
+    timeout: Duration,
+) -> std::result::Result<Option<MessageOrChannelPost>, telegram_bot::Error> {
+    let mut request = chat.photo(photo);
+    request.caption(caption);
+    if let Some(keyboard) = keyboard {
+        request.reply_markup(keyboard);
+    }
+    let response = outbox.send_timeout(Some(chat), request, timeout).await?;
+    debug!("responded with: {:?}", response);
+    Ok(response)
+}
+
+/// Uploads the image downloaded from its link, or from the mirror when it can't be downloaded
+async fn upload_photo(
+    database: &db::DbPool,
+    outbox: &Outbox,
+    chat: ChatId,
+    link: &Link,
+    caption: &str,
+    keyboard: Option<InlineKeyboardMarkup>,
+) -> Result<Option<MessageOrChannelPost>> {
+    let photo = match images::fetch_photo(&link.link, Duration::from_secs(30)).await {
+        Ok(photo) => photo,
+        Err(e) => match database.get().await?.mirrored_image(link.id)? {
+            Some(image) => {
+                warn!(
+                    "failed to download {}, uploading it from the mirror: {}",
+                    link.link, e
+                );
+                images::read_photo(&image.path).await?
+            }
+            None => return Err(e.into()),
+        },
+    };
+    let photo = InputFileUpload::with_data(photo.data, photo.file_name);
+    Ok(send(
+        outbox,
+        chat,
+        photo,
+        caption,
+        keyboard,
+        Duration::from_secs(30),
+    )
+    .await?)
+}
+
+#[test]
+fn test_is_fetch_error() {
+    assert!(is_fetch_error(
+        "Bad Request: failed to get HTTP URL content"
+    ));
+    assert!(is_fetch_error(
+        "Bad Request: wrong file identifier/HTTP URL specified"
+    ));
+    assert!(!is_fetch_error("Bad Request: chat not found"));
+}
@@ -0,0 +1,207 @@
+//! Telegram methods, or options of methods, that telegram_bot doesn't support
+
+use serde::Serialize;
+use telegram_bot::types::{
+    ChatId, Error, HttpRequest, InlineQueryId, JsonIdResponse, JsonRequestType,
+    JsonTrueToUnitResponse, MessageOrChannelPost, Request, RequestType, RequestUrl,
+};
+
+#[derive(Debug, Serialize)]
+pub struct InlinePhoto {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    photo_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thumb_url: Option<String>,
+    /// set instead of the urls for a photo already sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    photo_file_id: Option<String>,
+    title: String,
+    caption: String,
+}
+
+impl InlinePhoto {
+    pub fn new(id: String, photo_url: String, title: String) -> Self {
+        InlinePhoto {
+            kind: "photo",
+            id,
+            thumb_url: Some(photo_url.clone()),
+            photo_url: Some(photo_url),
+            photo_file_id: None,
+            caption: title.clone(),
+            title,
+        }
+    }
+
+    pub fn cached(id: String, file_id: String, title: String) -> Self {
+        InlinePhoto {
+            kind: "photo",
+            id,
+            photo_url: None,
+            thumb_url: None,
+            photo_file_id: Some(file_id),
+            caption: title.clone(),
+            title,
+        }
+    }
+}
+
+/// answerInlineQuery, with the pagination and caching options
+#[derive(Debug, Serialize)]
+pub struct AnswerInlineQuery {
+    inline_query_id: InlineQueryId,
+    results: Vec<InlinePhoto>,
+    cache_time: i64,
+    is_personal: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_offset: Option<String>,
+}
+
+impl AnswerInlineQuery {
+    pub fn new(
+        inline_query_id: InlineQueryId,
+        results: Vec<InlinePhoto>,
+        cache_time: i64,
+        next_offset: Option<String>,
+    ) -> Self {
+        AnswerInlineQuery {
+            inline_query_id,
+            results,
+            cache_time,
+            is_personal: false,
+            next_offset,
+        }
+    }
+
+    /// Caches the answer only for the user who sent the query
+    pub fn personal(mut self) -> Self {
+        self.is_personal = true;
+        self
+    }
+}
+
+impl Request for AnswerInlineQuery {
+    type Type = JsonRequestType<Self>;
+    type Response = JsonTrueToUnitResponse;
+
+    fn serialize(&self) -> Result<HttpRequest, Error> {
+        Self::Type::serialize(RequestUrl::method("answerInlineQuery"), self)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct InputMediaPhoto {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    media: String,
+    caption: String,
+}
+
+impl InputMediaPhoto {
+    pub fn new(media: String, caption: String) -> Self {
+        InputMediaPhoto {
+            kind: "photo",
+            media,
+            caption,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SendMediaGroup {
+    chat_id: ChatId,
+    media: Vec<InputMediaPhoto>,
+}
+
+impl SendMediaGroup {
+    pub fn new(chat_id: ChatId, media: Vec<InputMediaPhoto>) -> Self {
+        SendMediaGroup { chat_id, media }
+    }
+}
+
+impl Request for SendMediaGroup {
+    type Type = JsonRequestType<Self>;
+    type Response = JsonIdResponse<Vec<MessageOrChannelPost>>;
+
+    fn serialize(&self) -> Result<HttpRequest, Error> {
+        Self::Type::serialize(RequestUrl::method("sendMediaGroup"), self)
+    }
+}
+
+/// setWebhook, with the secret token Telegram sends back with each update
+#[derive(Debug, Serialize)]
+pub struct SetWebhook {
+    url: String,
+    secret_token: String,
+}
+
+impl SetWebhook {
+    pub fn new(url: String, secret_token: String) -> Self {
+        SetWebhook { url, secret_token }
+    }
+}
+
+impl Request for SetWebhook {
+    type Type = JsonRequestType<Self>;
+    type Response = JsonTrueToUnitResponse;
+
+    fn serialize(&self) -> Result<HttpRequest, Error> {
+        Self::Type::serialize(RequestUrl::method("setWebhook"), self)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteWebhook;
+
+impl Request for DeleteWebhook {
+    type Type = JsonRequestType<Self>;
+    type Response = JsonTrueToUnitResponse;
+
+    fn serialize(&self) -> Result<HttpRequest, Error> {
+        Self::Type::serialize(RequestUrl::method("deleteWebhook"), self)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BotCommand {
+    command: String,
+    description: String,
+}
+
+impl BotCommand {
+    pub fn new(command: String, description: String) -> Self {
+        BotCommand {
+            command,
+            description,
+        }
+    }
+}
+
+/// setMyCommands, the command menu of the Telegram clients, for the users of a language or
+/// the others
+#[derive(Debug, Serialize)]
+pub struct SetMyCommands {
+    commands: Vec<BotCommand>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    language_code: Option<&'static str>,
+}
+
+impl SetMyCommands {
+    pub fn new(commands: Vec<BotCommand>, language_code: Option<&'static str>) -> Self {
+        SetMyCommands {
+            commands,
+            language_code,
+        }
+    }
+}
+
+impl Request for SetMyCommands {
+    type Type = JsonRequestType<Self>;
+    type Response = JsonTrueToUnitResponse;
+
+    fn serialize(&self) -> Result<HttpRequest, Error> {
+        Self::Type::serialize(RequestUrl::method("setMyCommands"), self)
+    }
+}
@@ -0,0 +1,317 @@
+//! Daily images sent to subscribed chats
+
+use super::commands::{Args, CommandKind, Registry};
+use super::locale::{tr, Lang};
+use super::outbox::Outbox;
+use crate::db::{self, model::Subscription};
+use crate::search;
+use crate::selection;
+use crate::utils::unix_now;
+use crate::Result;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use telegram_bot::{prelude::CanReplySendMessage, types::ChatId, types::Message};
+use tokio::time;
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// Whether the part of a time is written with 1 or 2 digits, `str::parse` also takes a sign
+fn is_time_part(part: &str) -> bool {
+    (1..=2).contains(&part.len()) && part.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Minutes since midnight of a time written like 08:30
+fn parse_time_of_day(time: &str) -> Option<i32> {
+    let (hours, minutes) = time.split_once(':')?;
+    if !is_time_part(hours) || !is_time_part(minutes) {
+        return None;
+    }
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if (0..24).contains(&hours) && (0..60).contains(&minutes) {
+        Some(hours * 60 + minutes)
+    } else {
+        None
+    }
+}
+
+/// Minutes to add to UTC of an offset written like +02:00, -5, UTC+9 or UTC
+pub fn parse_utc_offset(offset: &str) -> Option<i32> {
+    let offset = offset.trim_start_matches("UTC");
+    if offset.is_empty() || offset == "0" {
+        return Some(0);
+    }
+    let sign = match offset.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let offset = &offset[1..];
+    let (hours, minutes) = offset.split_once(':').unwrap_or((offset, "0"));
+    if !is_time_part(hours) || !is_time_part(minutes) {
+        return None;
+    }
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    let total = sign * (hours * 60 + minutes);
+    if (0..60).contains(&minutes) && (-12 * 60..=14 * 60).contains(&total) {
+        Some(total)
+    } else {
+        None
+    }
+}
+
+pub fn format_utc_offset(offset: i32) -> String {
+    let sign = if offset < 0 { '-' } else { '+' };
+    format!(
+        "UTC{}{:02}:{:02}",
+        sign,
+        offset.abs() / 60,
+        offset.abs() % 60
+    )
+}
+
+fn format_time_of_day(time_of_day: i32) -> String {
+    format!("{:02}:{:02}", time_of_day / 60, time_of_day % 60)
+}
+
+/// First timestamp after `now` at the time of day, in a timezone `utc_offset` minutes from UTC
+pub fn next_occurrence(now: i64, time_of_day: i32, utc_offset: i32) -> i64 {
+    let offset = i64::from(utc_offset) * 60;
+    let local_now = now + offset;
+    let mut next = local_now - local_now.rem_euclid(DAY) + i64::from(time_of_day) * 60;
+    if next <= local_now {
+        next += DAY;
+    }
+    next - offset
+}
+
+async fn reply(outbox: &Outbox, message: &Message, text: String) -> Result<()> {
+    outbox
+        .send_timeout(
+            Some(message.chat.id()),
+            message.text_reply(text),
+            Duration::from_secs(5),
+        )
+        .await?;
+    Ok(())
+}
+
+pub async fn handle_subscribe(
+    database: db::DbPool,
+    outbox: Outbox,
+    message: Message,
+    args: Args,
+    commands: Arc<Registry>,
+) -> Result<()> {
+    let chat_id = message.chat.id().into();
+    let time = args.get("HH:MM").unwrap_or_default();
+    let query = args.get("search").unwrap_or_default();
+    let lang = super::chat_lang(&database, message.chat.id(), Some(&message.from)).await?;
+    let database = database.get().await?;
+    let settings = database.get_chat_settings(chat_id)?;
+    if time.is_empty() {
+        let text = match database.get_subscription(chat_id)? {
+            Some(subscription) => tr(
+                lang,
+                "subscription",
+                &[
+                    ("time", &format_time_of_day(subscription.time_of_day)),
+                    ("timezone", &format_utc_offset(settings.utc_offset)),
+                    ("query", &describe_query(&subscription.query, lang)),
+                ],
+            ),
+            None => {
+                let usage = commands.get(CommandKind::Subscribe).usage();
+                tr(lang, "usage", &[("usage", &usage)])
+            }
+        };
+        return reply(&outbox, &message, text).await;
+    }
+    let time_of_day = match parse_time_of_day(time) {
+        Some(time_of_day) => time_of_day,
+        None => {
+            let text = tr(lang, "subscribe-bad-time", &[("time", &time)]);
+            return reply(&outbox, &message, text).await;
+        }
+    };
+    if let Err(e) = search::parse(query) {
+        return reply(&outbox, &message, super::locale::search_error(lang, &e)).await;
+    }
+    database.subscribe(&Subscription {
+        chat_id,
+        time_of_day,
+        query: query.to_owned(),
+        next_at: next_occurrence(unix_now(), time_of_day, settings.utc_offset),
+    })?;
+    let text = tr(
+        lang,
+        "subscribed",
+        &[
+            ("time", &format_time_of_day(time_of_day)),
+            ("timezone", &format_utc_offset(settings.utc_offset)),
+            ("query", &describe_query(query, lang)),
+            ("settings", &commands.get(CommandKind::Settings).name),
+        ],
+    );
+    reply(&outbox, &message, text).await
+}
+
+fn describe_query(query: &str, lang: Lang) -> String {
+    if query.is_empty() {
+        String::new()
+    } else {
+        tr(lang, "subscription-query", &[("query", &query)])
+    }
+}
+
+pub async fn handle_unsubscribe(
+    database: db::DbPool,
+    outbox: Outbox,
+    message: Message,
+) -> Result<()> {
+    let lang = super::chat_lang(&database, message.chat.id(), Some(&message.from)).await?;
+    let key = if database
+        .get()
+        .await?
+        .unsubscribe(message.chat.id().into())?
+    {
+        "unsubscribed"
+    } else {
+        "not-subscribed"
+    };
+    reply(&outbox, &message, tr(lang, key, &[])).await
+}
+
+/// Reschedules the chat's subscription after its timezone changed
+pub fn reschedule(database: &db::Database, chat_id: i64, utc_offset: i32) -> Result<()> {
+    if let Some(subscription) = database.get_subscription(chat_id)? {
+        let next_at = next_occurrence(unix_now(), subscription.time_of_day, utc_offset);
+        database.set_next_at(chat_id, next_at)?;
+    }
+    Ok(())
+}
+
+async fn send_subscription(
+    database: &db::DbPool,
+    outbox: &Outbox,
+    weights: &selection::Weights,
+    subscription: &Subscription,
+) -> Result<()> {
+    let chat_id = ChatId::new(subscription.chat_id);
+    let search = match search::parse(&subscription.query) {
+        Ok(search) => search,
+        Err(e) => {
+            warn!(
+                "invalid query in the subscription of chat {}: {}",
+                subscription.chat_id, e
+            );
+            return Ok(());
+        }
+    };
+    let lang = super::chat_lang(database, chat_id, None).await?;
+    match super::pick_links(database, weights, chat_id, &search, 1)
+        .await?
+        .pop()
+    {
+        Some(link) => {
+            super::send_link_or_another(
+                database,
+                outbox,
+                chat_id,
+                None,
+                link,
+                &subscription.query,
+                lang,
+            )
+            .await
+        }
+        None => {
+            warn!(
+                "no image for the subscription of chat {}",
+                subscription.chat_id
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Sends the images of the subscriptions when they are due. Subscriptions missed while the bot
+/// was stopped are sent when it restarts
+pub async fn run_scheduler(db_pool: db::DbPool, outbox: Outbox, weights: selection::Weights) {
+    let mut interval = time::interval(Duration::from_secs(30));
+    loop {
+        interval.tick().await;
+        let now = unix_now();
+        let due = match db_pool.get().await {
+            Ok(database) => database.due_subscriptions(now),
+            Err(e) => Err(e),
+        };
+        let due = match due {
+            Ok(due) => due,
+            Err(e) => {
+                error!("failed to get the due subscriptions: {}", e);
+                continue;
+            }
+        };
+        for subscription in due {
+            if let Err(e) = send_subscription(&db_pool, &outbox, &weights, &subscription).await {
+                error!(
+                    "failed to send the subscription of chat {}: {}",
+                    subscription.chat_id, e
+                );
+            }
+            let rescheduled = match db_pool.get().await {
+                Ok(database) => {
+                    let utc_offset = database
+                        .get_chat_settings(subscription.chat_id)
+                        .map(|settings| settings.utc_offset)
+                        .unwrap_or(0);
+                    database.set_next_at(
+                        subscription.chat_id,
+                        next_occurrence(now, subscription.time_of_day, utc_offset),
+                    )
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = rescheduled {
+                error!(
+                    "failed to reschedule the subscription of chat {}: {}",
+                    subscription.chat_id, e
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_parse_times() {
+    assert_eq!(Some(8 * 60 + 30), parse_time_of_day("08:30"));
+    assert_eq!(Some(0), parse_time_of_day("0:00"));
+    assert_eq!(None, parse_time_of_day("24:00"));
+    assert_eq!(None, parse_time_of_day("8h30"));
+    assert_eq!(None, parse_time_of_day("+8:30"));
+    assert_eq!(None, parse_time_of_day("08:+5"));
+    assert_eq!(Some(120), parse_utc_offset("+02:00"));
+    assert_eq!(Some(-330), parse_utc_offset("UTC-5:30"));
+    assert_eq!(Some(0), parse_utc_offset("UTC"));
+    assert_eq!(None, parse_utc_offset("+15"));
+    assert_eq!(None, parse_utc_offset("+-2"));
+    assert_eq!(None, parse_utc_offset("+2:+30"));
+    assert_eq!("UTC-05:30", format_utc_offset(-330));
+}
+
+#[test]
+fn test_next_occurrence() {
+    // 2024-06-01 10:00 UTC
+    let now = 1_717_236_000;
+    assert_eq!(now + 30 * 60, next_occurrence(now, 10 * 60 + 30, 0));
+    assert_eq!(now + 23 * 60 * 60, next_occurrence(now, 9 * 60, 0));
+    // 08:00 in UTC+2 is 06:00 UTC, so tomorrow
+    assert_eq!(now + 20 * 60 * 60, next_occurrence(now, 8 * 60, 120));
+    // 08:00 in UTC-5 is 13:00 UTC
+    assert_eq!(now + 3 * 60 * 60, next_occurrence(now, 8 * 60, -300));
+    assert_eq!(now + DAY, next_occurrence(now, 10 * 60, 0));
+}
@@ -0,0 +1,119 @@
+//! Limits of the commands each user and each chat can send
+
+use crate::rate_limit::RateLimiter;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Highest limit a chat can set for itself
+pub const MAX_COMMANDS_PER_MINUTE: i32 = 60;
+const MINUTE: Duration = Duration::from_secs(60);
+
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Allowed,
+    /// throttled, and the user should be told to slow down
+    Notify,
+    /// throttled, and the user has already been told
+    Silent,
+}
+
+#[derive(Debug)]
+pub struct Throttle {
+    users: RateLimiter<i64>,
+    chats: RateLimiter<i64>,
+    /// commands per minute of the chats without an override
+    chat_limit: u32,
+    chat_overrides: Mutex<HashMap<i64, u32>>,
+    /// when users were told to slow down in a chat, until one of their commands is allowed
+    /// again there or a minute has passed
+    notified: Mutex<HashMap<(i64, i64), Instant>>,
+}
+
+impl Throttle {
+    pub fn new(user_limit: u32, chat_limit: u32, chat_overrides: HashMap<i64, u32>) -> Self {
+        Throttle {
+            users: RateLimiter::new(user_limit, MINUTE),
+            chats: RateLimiter::new(chat_limit, MINUTE),
+            chat_limit,
+            chat_overrides: Mutex::new(chat_overrides),
+            notified: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn chat_limit(&self, chat_id: i64) -> u32 {
+        self.chat_overrides
+            .lock()
+            .unwrap()
+            .get(&chat_id)
+            .copied()
+            .unwrap_or(self.chat_limit)
+    }
+
+    pub fn set_chat_limit(&self, chat_id: i64, limit: Option<u32>) {
+        let mut overrides = self.chat_overrides.lock().unwrap();
+        match limit {
+            Some(limit) => overrides.insert(chat_id, limit),
+            None => overrides.remove(&chat_id),
+        };
+    }
+
+    pub fn check(&self, user_id: i64, chat_id: i64) -> Verdict {
+        self.check_at(user_id, chat_id, Instant::now())
+    }
+
+    pub fn check_at(&self, user_id: i64, chat_id: i64, now: Instant) -> Verdict {
+        let chat_limit = self.chat_limit(chat_id);
+        let mut allowed = self.users.check_at(user_id, now);
+        if allowed && !self.chats.check_capacity_at(chat_id, chat_limit, now) {
+            // The command isn't sent, so it doesn't count against the user
+            self.users.refund(&user_id);
+            allowed = false;
+        }
+        let mut notified = self.notified.lock().unwrap();
+        // The users who don't come back are forgotten too
+        notified.retain(|_, notified_at| now.saturating_duration_since(*notified_at) < MINUTE);
+        if allowed {
+            notified.remove(&(user_id, chat_id));
+            Verdict::Allowed
+        } else if notified.insert((user_id, chat_id), now).is_none() {
+            Verdict::Notify
+        } else {
+            Verdict::Silent
+        }
+    }
+}
+
+#[test]
+fn test_throttle() {
+    let throttle = Throttle::new(2, 3, HashMap::new());
+    let start = Instant::now();
+    assert_eq!(Verdict::Allowed, throttle.check_at(1, -10, start));
+    assert_eq!(Verdict::Allowed, throttle.check_at(1, -10, start));
+    assert_eq!(Verdict::Notify, throttle.check_at(1, -10, start));
+    assert_eq!(Verdict::Silent, throttle.check_at(1, -10, start));
+    // The chat has one command left
+    assert_eq!(Verdict::Allowed, throttle.check_at(2, -10, start));
+    assert_eq!(Verdict::Notify, throttle.check_at(3, -10, start));
+    assert_eq!(Verdict::Allowed, throttle.check_at(3, -20, start));
+    // The command denied in the full chat didn't take a token from user 3
+    assert_eq!(Verdict::Allowed, throttle.check_at(3, -20, start));
+    // User 1 is told again in another chat
+    assert_eq!(Verdict::Notify, throttle.check_at(1, -20, start));
+    // After a minute, user 1 is allowed and will be told again
+    let later = start + Duration::from_secs(60);
+    assert_eq!(Verdict::Allowed, throttle.check_at(1, -10, later));
+    assert_eq!(Verdict::Allowed, throttle.check_at(1, -10, later));
+    assert_eq!(Verdict::Notify, throttle.check_at(1, -10, later));
+
+    throttle.set_chat_limit(-30, Some(1));
+    assert_eq!(1, throttle.chat_limit(-30));
+    assert_eq!(Verdict::Allowed, throttle.check_at(4, -30, start));
+    assert_eq!(Verdict::Notify, throttle.check_at(5, -30, start));
+    throttle.set_chat_limit(-30, None);
+    assert_eq!(3, throttle.chat_limit(-30));
+    // The users told to slow down are forgotten after a minute
+    assert_eq!(Verdict::Allowed, throttle.check_at(6, -40, later + MINUTE));
+    assert!(throttle.notified.lock().unwrap().is_empty());
+}
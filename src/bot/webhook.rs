@@ -0,0 +1,193 @@
+//! Webhook mode, where Telegram sends the updates to a local HTTP listener instead of being
+//! polled. The listener is plain HTTP, meant to be behind a reverse proxy terminating HTTPS
+
+use super::requests::{DeleteWebhook, SetWebhook};
+use crate::Result;
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::{
+    header::HeaderValue,
+    service::{make_service_fn, service_fn},
+    Body, HeaderMap, Method, Request, Response, Server, StatusCode,
+};
+use telegram_bot::{types::Update, Api};
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Header in which Telegram sends back the secret token given to setWebhook
+const SECRET_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// public HTTPS url Telegram sends the updates to
+    pub url: String,
+    pub listen: SocketAddr,
+    pub secret_token: String,
+}
+
+fn is_authorized(headers: &HeaderMap, secret_token: &str) -> bool {
+    headers.get(SECRET_HEADER).map(HeaderValue::as_bytes) == Some(secret_token.as_bytes())
+}
+
+/// Returns `None` for the updates that can't be read, which are acknowledged anyway since
+/// Telegram would otherwise keep sending them
+fn parse_update(
+    headers: &HeaderMap,
+    secret_token: &str,
+    body: &[u8],
+) -> std::result::Result<Option<Update>, StatusCode> {
+    if !is_authorized(headers, secret_token) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    match serde_json::from_slice(body) {
+        Ok(update) => Ok(Some(update)),
+        Err(e) => {
+            warn!(
+                "ignoring an update sent to the webhook: {}: {}",
+                e,
+                String::from_utf8_lossy(body)
+            );
+            Ok(None)
+        }
+    }
+}
+
+async fn handle_request(
+    request: Request<Body>,
+    secret_token: Arc<String>,
+    updates: UnboundedSender<Update>,
+) -> std::result::Result<Response<Body>, Infallible> {
+    let status = if request.method() != Method::POST {
+        StatusCode::METHOD_NOT_ALLOWED
+    } else {
+        let (parts, body) = request.into_parts();
+        match hyper::body::to_bytes(body).await {
+            Ok(body) => match parse_update(&parts.headers, &secret_token, &body) {
+                Ok(update) => {
+                    if let Some(update) = update {
+                        // The receiver only goes away once the server stopped
+                        let _ = updates.send(update);
+                    }
+                    StatusCode::OK
+                }
+                Err(status) => status,
+            },
+            Err(e) => {
+                debug!("failed to read a webhook request: {}", e);
+                StatusCode::BAD_REQUEST
+            }
+        }
+    };
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    Ok(response)
+}
+
+/// Registers the webhook, then forwards the updates it receives until the bot is stopped with
+/// ctrl-c, and unregisters it
+pub async fn serve(
+    api: Api,
+    config: WebhookConfig,
+    updates: UnboundedSender<Update>,
+) -> Result<()> {
+    let secret_token = Arc::new(config.secret_token);
+    let set_webhook = SetWebhook::new(config.url.clone(), secret_token.to_string());
+    let make_service = make_service_fn(move |_| {
+        let secret_token = secret_token.clone();
+        let updates = updates.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle_request(request, secret_token.clone(), updates.clone())
+            }))
+        }
+    });
+    let server = Server::try_bind(&config.listen)?.serve(make_service);
+    api.send_timeout(set_webhook, Duration::from_secs(10))
+        .await?;
+    info!("receiving updates on {} for {}", config.listen, config.url);
+    let result = server
+        .with_graceful_shutdown(async {
+            if let Err(e) = tokio::signal::ctrl_c().await {
+                error!("failed to listen for ctrl-c: {}", e);
+                futures::future::pending::<()>().await;
+            }
+        })
+        .await;
+    info!("stopping the webhook");
+    api.send_timeout(DeleteWebhook, Duration::from_secs(10))
+        .await?;
+    Ok(result?)
+}
+
+#[test]
+fn test_parse_update() {
+    let mut headers = HeaderMap::new();
+    let body = br#"{"update_id": 1}"#;
+    assert_eq!(
+        Some(StatusCode::UNAUTHORIZED),
+        parse_update(&headers, "secret", body).err()
+    );
+    headers.insert(SECRET_HEADER, HeaderValue::from_static("wrong"));
+    assert_eq!(
+        Some(StatusCode::UNAUTHORIZED),
+        parse_update(&headers, "secret", body).err()
+    );
+    headers.insert(SECRET_HEADER, HeaderValue::from_static("secret"));
+    assert!(matches!(
+        parse_update(&headers, "secret", b"not json"),
+        Ok(None)
+    ));
+}
+
+#[test]
+fn test_handle_request_dispatches_updates() {
+    use telegram_bot::types::{MessageKind, UpdateKind};
+    use tokio::sync::mpsc;
+
+    let update = r#"{
+        "update_id": 10000,
+        "message": {
+            "message_id": 1365,
+            "from": {"id": 1111111, "is_bot": false, "first_name": "Touko", "language_code": "en"},
+            "chat": {"id": 1111111, "first_name": "Touko", "type": "private"},
+            "date": 1441645532,
+            "text": "/more maid"
+        }
+    }"#;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let request = |body: &'static str| {
+        Request::post("/")
+            .header(SECRET_HEADER, "secret")
+            .body(Body::from(body))
+            .unwrap()
+    };
+    let secret_token = Arc::new("secret".to_owned());
+    let response = runtime
+        .block_on(handle_request(
+            request(update),
+            secret_token.clone(),
+            sender.clone(),
+        ))
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    let update = receiver.try_recv().unwrap();
+    assert_eq!(10000, update.id);
+    match update.kind {
+        UpdateKind::Message(message) => match message.kind {
+            MessageKind::Text { data, .. } => assert_eq!("/more maid", data),
+            kind => panic!("unexpected message kind {:?}", kind),
+        },
+        kind => panic!("unexpected update kind {:?}", kind),
+    }
+    let response = runtime
+        .block_on(handle_request(request("not json"), secret_token, sender))
+        .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+    assert!(receiver.try_recv().is_err());
+}
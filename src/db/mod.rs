@@ -3,23 +3,113 @@ pub mod model;
 mod schema;
 
 use async_trait::async_trait;
-use diesel::{dsl::Asc, prelude::*};
+use diesel::{
+    dsl::{sql, Asc},
+    prelude::*,
+    sql_types::{BigInt, Integer},
+    sqlite::Sqlite,
+};
 use errors::{DatabaseError, Result};
 
+use crate::search::{self, Orientation, Search};
+
 no_arg_sql_function!(RANDOM, (), "Represents the sql RANDOM() function");
 
-fn escape_fts(search: &str) -> String {
-    let mut escaped = String::with_capacity(search.len() + 2);
-    escaped.push('"');
-    for c in search.chars() {
-        if c == '"' {
-            escaped.push_str("\"\"");
-        } else {
-            escaped.push(c);
+type LinkColumns = (
+    schema::links::id,
+    schema::links::link,
+    schema::links::title,
+    schema::links::permalink,
+    schema::links::file_id,
+);
+const LINK_COLUMNS: LinkColumns = (
+    schema::links::id,
+    schema::links::link,
+    schema::links::title,
+    schema::links::permalink,
+    schema::links::file_id,
+);
+
+/// Links matching the words and the filters of a search, to select from
+fn matching_links(search: &Search) -> schema::links::BoxedQuery<'static, Sqlite> {
+    use schema::{links, links_cjk_idx, links_title_idx};
+    // Links whose image couldn't be sent several times, and reposts, are left out
+    let mut query = links::table
+        .filter(links::quarantined_at.is_null())
+        .filter(links::duplicate_of.is_null())
+        .into_boxed();
+    if let Some(fts) = search.fts().filter(|_| search.is_cjk()) {
+        query = query.filter(
+            links::id.eq_any(
+                links_cjk_idx::table
+                    .select(links_cjk_idx::rowid)
+                    .filter(links_cjk_idx::whole_row.eq(fts.to_owned())),
+            ),
+        );
+    } else if let Some(fts) = search.fts() {
+        query = query.filter(
+            links::id.eq_any(
+                links_title_idx::table
+                    .select(links_title_idx::id)
+                    .filter(links_title_idx::whole_row.eq(fts.to_owned())),
+            ),
+        );
+    }
+    if let Some(subreddit) = &search.subreddit {
+        query = query.filter(links::subreddit.eq(subreddit.clone()));
+    }
+    // Links scraped before the post dates were stored have a date of 0, which is no date
+    if let Some(before) = search.before {
+        query = query
+            .filter(links::created_utc.gt(0))
+            .filter(links::created_utc.lt(before));
+    }
+    if let Some(after) = search.after {
+        query = query.filter(links::created_utc.ge(after));
+    }
+    if let Some(media_type) = &search.media_type {
+        query = query.filter(links::media_type.eq(media_type.clone()));
+    }
+    if let Some(min_score) = search.min_score {
+        query = query.filter(links::score.ge(min_score));
+    }
+    if let Some(max_score) = search.max_score {
+        query = query.filter(links::score.le(max_score));
+    }
+    if let Some(artist) = &search.artist {
+        query = query.filter(links::artist.like(contains_pattern(artist)).escape('\\'));
+    }
+    if let Some(series) = &search.series {
+        query = query.filter(links::series.like(contains_pattern(series)).escape('\\'));
+    }
+    match search.orientation {
+        Some(Orientation::Portrait) => query = query.filter(links::height.gt(links::width)),
+        Some(Orientation::Landscape) => query = query.filter(links::width.gt(links::height)),
+        None => {}
+    }
+    // Images whose size isn't known yet are left out, they could be thumbnails
+    if let Some(min_resolution) = search.min_resolution {
+        query = query.filter(
+            links::width
+                .ge(min_resolution)
+                .and(links::height.ge(min_resolution)),
+        );
+    }
+    query
+}
+
+/// LIKE pattern matching the text anywhere, escaped with `\`
+fn contains_pattern(text: &str) -> String {
+    let mut pattern = String::with_capacity(text.len() + 2);
+    pattern.push('%');
+    for c in text.chars() {
+        if c == '%' || c == '_' || c == '\\' {
+            pattern.push('\\');
         }
+        pattern.push(c);
     }
-    escaped.push('"');
-    escaped
+    pattern.push('%');
+    pattern
 }
 
 pub struct Database {
@@ -35,7 +125,19 @@ impl Database {
 
     #[allow(dead_code)]
     pub fn insert_link<'a>(&self, link: &'a str, title: &'a str) -> Result<usize> {
-        let new_link = model::NewLink { link, title };
+        let new_link = model::NewLink {
+            link,
+            title,
+            score: 0,
+            created_utc: 0,
+            permalink: None,
+            subreddit: None,
+            media_type: None,
+            artist: None,
+            series: None,
+            width: None,
+            height: None,
+        };
         diesel::insert_or_ignore_into(schema::links::table)
             .values(new_link)
             .execute(&self.connection)
@@ -43,55 +145,713 @@ impl Database {
     }
 
     pub fn insert_links<'a>(&self, new_links: &[model::NewLink<'a>]) -> Result<usize> {
-        diesel::insert_or_ignore_into(schema::links::table)
+        let insert_count = diesel::insert_or_ignore_into(schema::links::table)
             .values(new_links)
-            .execute(&self.connection)
-            .map_err(|e| e.into())
+            .execute(&self.connection)?;
+        self.index_cjk_titles()?;
+        Ok(insert_count)
+    }
+
+    /// Adds the titles missing from the CJK full text index, which can't be filled by a trigger
+    /// since the bigrams are computed by `search::cjk_grams`
+    pub fn index_cjk_titles(&self) -> Result<usize> {
+        use schema::{links, links_cjk_idx};
+        self.connection.transaction(|| {
+            let titles: Vec<(i32, String)> = links::table
+                .select((links::id, links::title))
+                .filter(diesel::dsl::not(
+                    links::id.eq_any(links_cjk_idx::table.select(links_cjk_idx::rowid)),
+                ))
+                .get_results(&self.connection)?;
+            for (id, title) in &titles {
+                diesel::insert_into(links_cjk_idx::table)
+                    .values((
+                        links_cjk_idx::rowid.eq(id),
+                        links_cjk_idx::grams.eq(search::cjk_grams(title)),
+                    ))
+                    .execute(&self.connection)?;
+            }
+            Ok(titles.len())
+        })
+    }
+
+    /// Refreshes the score, date, permalink and subreddit of links that were scrapped before
+    pub fn refresh_links<'a>(&self, new_links: &[model::NewLink<'a>]) -> Result<()> {
+        use schema::links;
+        for new_link in new_links {
+            diesel::update(
+                links::table
+                    .filter(links::link.eq(new_link.link))
+                    .filter(links::title.eq(new_link.title)),
+            )
+            .set((
+                links::score.eq(new_link.score),
+                links::created_utc.eq(new_link.created_utc),
+                links::permalink.eq(new_link.permalink),
+                links::subreddit.eq(new_link.subreddit),
+            ))
+            .execute(&self.connection)?;
+        }
+        Ok(())
     }
 
-    pub fn fetch_random_link(&self) -> Result<model::Link> {
-        use schema::links::dsl::*;
-        links
+    pub fn fetch_random_links(&self, search: &Search, limit: i64) -> Result<Vec<model::Link>> {
+        matching_links(search)
+            .select(LINK_COLUMNS)
             .order(RANDOM)
-            .limit(1)
+            .limit(limit)
+            .get_results(&self.connection)
+            .map_err(|e| e.into())
+    }
+
+    pub fn count_links(&self, search: &Search) -> Result<i64> {
+        matching_links(search)
+            .count()
             .first(&self.connection)
             .map_err(|e| e.into())
     }
 
-    pub fn count_links(&self) -> Result<i64> {
-        use schema::links::dsl::*;
-        links.count().first(&self.connection).map_err(|e| e.into())
+    /// Links matching the search, newest first, for paginated results
+    pub fn search_page(
+        &self,
+        search: &Search,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<model::Link>> {
+        use schema::links;
+        matching_links(search)
+            .select(LINK_COLUMNS)
+            .order(links::id.desc())
+            .offset(offset)
+            .limit(limit)
+            .get_results(&self.connection)
+            .map_err(|e| e.into())
+    }
+
+    pub fn get_link(&self, link_id: i32) -> Result<model::Link> {
+        use schema::links;
+        links::table
+            .select(LINK_COLUMNS)
+            .filter(links::id.eq(link_id))
+            .first(&self.connection)
+            .map_err(|e| e.into())
     }
 
-    pub fn search_random_full_text(&self, search: &str) -> Result<Option<model::Link>> {
-        use schema::links_title_idx;
-        links_title_idx::table
-            .select((
-                links_title_idx::id,
-                links_title_idx::link,
-                links_title_idx::title,
+    pub fn fetch_candidates(&self, search: &Search) -> Result<Vec<model::Candidate>> {
+        use schema::links;
+        matching_links(search)
+            .select((links::id, links::score, links::created_utc))
+            .get_results(&self.connection)
+            .map_err(|e| e.into())
+    }
+
+    /// Words of the full text index, the most common first
+    pub fn vocabulary(&self) -> Result<Vec<String>> {
+        use schema::links_title_vocab;
+        links_title_vocab::table
+            .select(links_title_vocab::term)
+            .order(links_title_vocab::doc.desc())
+            .get_results(&self.connection)
+            .map_err(|e| e.into())
+    }
+
+    pub fn vote(&self, link_id: i32, user_id: i64, value: i32, voted_at: i64) -> Result<()> {
+        use schema::votes;
+        diesel::replace_into(votes::table)
+            .values((
+                votes::link_id.eq(link_id),
+                votes::user_id.eq(user_id),
+                votes::value.eq(value),
+                votes::voted_at.eq(voted_at),
             ))
-            .filter(links_title_idx::whole_row.eq(escape_fts(search)))
-            .order(RANDOM)
-            .limit(1)
+            .execute(&self.connection)?;
+        Ok(())
+    }
+
+    /// Sum of the votes of every link that received some
+    pub fn vote_totals(&self) -> Result<Vec<(i32, i64)>> {
+        use schema::votes;
+        votes::table
+            .group_by(votes::link_id)
+            .select((votes::link_id, sql::<BigInt>("SUM(value)")))
+            .get_results(&self.connection)
+            .map_err(|e| e.into())
+    }
+
+    /// Ids of the best voted links matching the search, counting only votes cast since `since`
+    pub fn top_links(&self, search: &Search, since: i64, limit: i64) -> Result<Vec<i32>> {
+        use schema::{links, votes};
+        // Even without a search, quarantined links and reposts are left out
+        let top: Vec<(i32, i64)> = votes::table
+            .filter(votes::voted_at.ge(since))
+            .filter(votes::link_id.eq_any(matching_links(search).select(links::id)))
+            .group_by(votes::link_id)
+            .select((votes::link_id, sql::<BigInt>("SUM(value)")))
+            .order(sql::<BigInt>("SUM(value) DESC"))
+            .limit(limit)
+            .get_results(&self.connection)?;
+        Ok(top
+            .into_iter()
+            .filter(|(_, total)| *total > 0)
+            .map(|(link_id, _)| link_id)
+            .collect())
+    }
+
+    /// Adds the link to the user's favorites, or removes it if it already is one.
+    /// Returns whether it was added, `None` if the link isn't in the database anymore
+    pub fn toggle_favorite(
+        &self,
+        user_id: i64,
+        link_id: i32,
+        added_at: i64,
+    ) -> Result<Option<bool>> {
+        use schema::{favorites, links};
+        self.connection.transaction(|| {
+            let link: Option<model::Link> = links::table
+                .select(LINK_COLUMNS)
+                .filter(links::id.eq(link_id))
+                .first(&self.connection)
+                .optional()?;
+            let Some(link) = link else {
+                return Ok(None);
+            };
+            let removed = diesel::delete(
+                favorites::table
+                    .filter(favorites::user_id.eq(user_id))
+                    .filter(favorites::link_id.eq(link_id))
+                    .filter(favorites::link.eq(&link.link)),
+            )
+            .execute(&self.connection)?;
+            if removed > 0 {
+                return Ok(Some(false));
+            }
+            // Replaces the dead favorite whose link had the id of this one before a purge
+            diesel::replace_into(favorites::table)
+                .values(model::Favorite {
+                    user_id,
+                    link_id: link.id,
+                    link: link.link,
+                    title: link.title,
+                    added_at,
+                })
+                .execute(&self.connection)?;
+            Ok(Some(true))
+        })
+    }
+
+    pub fn count_favorites(&self, user_id: i64) -> Result<i64> {
+        use schema::favorites;
+        favorites::table
+            .filter(favorites::user_id.eq(user_id))
+            .count()
+            .first(&self.connection)
+            .map_err(|e| e.into())
+    }
+
+    /// The user's favorites, last added first, with whether their link is still in the database
+    pub fn favorites_page(
+        &self,
+        user_id: i64,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<(model::Favorite, bool)>> {
+        use schema::{favorites, links};
+        // The ids of the purged links get reused, the favorite's link is the same if it is alive
+        let page: Vec<(model::Favorite, Option<i32>)> = favorites::table
+            .left_join(
+                links::table.on(links::id
+                    .eq(favorites::link_id)
+                    .and(links::link.eq(favorites::link))),
+            )
+            .select((favorites::all_columns, links::id.nullable()))
+            .filter(favorites::user_id.eq(user_id))
+            .order(favorites::added_at.desc())
+            .offset(offset)
+            .limit(limit)
+            .get_results(&self.connection)?;
+        Ok(page
+            .into_iter()
+            .map(|(favorite, link_id)| (favorite, link_id.is_some()))
+            .collect())
+    }
+
+    /// A random favorite of the user, with its link if it is still in the database. A dead
+    /// favorite is only returned when all of them are dead
+    pub fn random_favorite(
+        &self,
+        user_id: i64,
+    ) -> Result<Option<(model::Favorite, Option<model::Link>)>> {
+        use schema::{favorites, links};
+        favorites::table
+            .left_join(
+                links::table.on(links::id
+                    .eq(favorites::link_id)
+                    .and(links::link.eq(favorites::link))),
+            )
+            .select((favorites::all_columns, LINK_COLUMNS.nullable()))
+            .filter(favorites::user_id.eq(user_id))
+            .order((links::id.is_null(), RANDOM))
             .first(&self.connection)
             .optional()
             .map_err(|e| e.into())
     }
 
-    pub fn count_links_search(&self, search: &str) -> Result<i64> {
-        use schema::links_title_idx;
-        links_title_idx::table
-            .count()
-            .filter(links_title_idx::whole_row.eq(escape_fts(search)))
+    pub fn get_chat_settings(&self, chat_id: i64) -> Result<model::ChatSettings> {
+        use schema::chat_settings;
+        Ok(chat_settings::table
+            .filter(chat_settings::chat_id.eq(chat_id))
+            .first(&self.connection)
+            .optional()?
+            .unwrap_or_else(|| model::ChatSettings::default_for(chat_id)))
+    }
+
+    /// Creates the chat's settings row with the default values if it doesn't exist yet
+    fn ensure_chat_settings(&self, chat_id: i64) -> Result<()> {
+        use schema::chat_settings;
+        diesel::insert_or_ignore_into(chat_settings::table)
+            .values(chat_settings::chat_id.eq(chat_id))
+            .execute(&self.connection)?;
+        Ok(())
+    }
+
+    pub fn set_selection_mode(&self, chat_id: i64, mode: &str) -> Result<()> {
+        use schema::chat_settings;
+        self.ensure_chat_settings(chat_id)?;
+        diesel::update(chat_settings::table.filter(chat_settings::chat_id.eq(chat_id)))
+            .set(chat_settings::selection_mode.eq(mode))
+            .execute(&self.connection)?;
+        Ok(())
+    }
+
+    pub fn set_max_album_size(&self, chat_id: i64, size: i32) -> Result<()> {
+        use schema::chat_settings;
+        self.ensure_chat_settings(chat_id)?;
+        diesel::update(chat_settings::table.filter(chat_settings::chat_id.eq(chat_id)))
+            .set(chat_settings::max_album_size.eq(size))
+            .execute(&self.connection)?;
+        Ok(())
+    }
+
+    pub fn set_utc_offset(&self, chat_id: i64, utc_offset: i32) -> Result<()> {
+        use schema::chat_settings;
+        self.ensure_chat_settings(chat_id)?;
+        diesel::update(chat_settings::table.filter(chat_settings::chat_id.eq(chat_id)))
+            .set(chat_settings::utc_offset.eq(utc_offset))
+            .execute(&self.connection)?;
+        Ok(())
+    }
+
+    pub fn set_language(&self, chat_id: i64, language: Option<&str>) -> Result<()> {
+        use schema::chat_settings;
+        self.ensure_chat_settings(chat_id)?;
+        diesel::update(chat_settings::table.filter(chat_settings::chat_id.eq(chat_id)))
+            .set(chat_settings::language.eq(language))
+            .execute(&self.connection)?;
+        Ok(())
+    }
+
+    pub fn set_commands_per_minute(&self, chat_id: i64, limit: Option<i32>) -> Result<()> {
+        use schema::chat_settings;
+        self.ensure_chat_settings(chat_id)?;
+        diesel::update(chat_settings::table.filter(chat_settings::chat_id.eq(chat_id)))
+            .set(chat_settings::commands_per_minute.eq(limit))
+            .execute(&self.connection)?;
+        Ok(())
+    }
+
+    pub fn set_min_resolution(&self, chat_id: i64, min_resolution: Option<i32>) -> Result<()> {
+        use schema::chat_settings;
+        self.ensure_chat_settings(chat_id)?;
+        diesel::update(chat_settings::table.filter(chat_settings::chat_id.eq(chat_id)))
+            .set(chat_settings::min_resolution.eq(min_resolution))
+            .execute(&self.connection)?;
+        Ok(())
+    }
+
+    /// Chats that override the configured limit of commands per minute, with their limit
+    pub fn chat_command_limits(&self) -> Result<Vec<(i64, i32)>> {
+        use schema::chat_settings;
+        let limits: Vec<(i64, Option<i32>)> = chat_settings::table
+            .select((chat_settings::chat_id, chat_settings::commands_per_minute))
+            .filter(chat_settings::commands_per_minute.is_not_null())
+            .load(&self.connection)?;
+        Ok(limits
+            .into_iter()
+            .filter_map(|(chat_id, limit)| Some((chat_id, limit?)))
+            .collect())
+    }
+
+    /// Subscribes the chat, replacing its previous subscription
+    pub fn subscribe(&self, subscription: &model::Subscription) -> Result<()> {
+        use schema::subscriptions;
+        diesel::replace_into(subscriptions::table)
+            .values(subscription)
+            .execute(&self.connection)?;
+        Ok(())
+    }
+
+    /// Returns whether the chat was subscribed
+    pub fn unsubscribe(&self, chat_id: i64) -> Result<bool> {
+        use schema::subscriptions;
+        let deleted =
+            diesel::delete(subscriptions::table.filter(subscriptions::chat_id.eq(chat_id)))
+                .execute(&self.connection)?;
+        Ok(deleted > 0)
+    }
+
+    pub fn get_subscription(&self, chat_id: i64) -> Result<Option<model::Subscription>> {
+        use schema::subscriptions;
+        subscriptions::table
+            .filter(subscriptions::chat_id.eq(chat_id))
             .first(&self.connection)
+            .optional()
+            .map_err(|e| e.into())
+    }
+
+    /// Subscriptions whose image should have been sent by `now`
+    pub fn due_subscriptions(&self, now: i64) -> Result<Vec<model::Subscription>> {
+        use schema::subscriptions;
+        subscriptions::table
+            .filter(subscriptions::next_at.le(now))
+            .get_results(&self.connection)
             .map_err(|e| e.into())
     }
 
+    pub fn set_next_at(&self, chat_id: i64, next_at: i64) -> Result<()> {
+        use schema::subscriptions;
+        diesel::update(subscriptions::table.filter(subscriptions::chat_id.eq(chat_id)))
+            .set(subscriptions::next_at.eq(next_at))
+            .execute(&self.connection)?;
+        Ok(())
+    }
+
+    /// Id of the last inserted link, 0 if there is none
+    pub fn last_link_id(&self) -> Result<i32> {
+        use schema::links;
+        let last: Option<i32> = links::table
+            .select(diesel::dsl::max(links::id))
+            .first(&self.connection)?;
+        Ok(last.unwrap_or(0))
+    }
+
+    /// Queues the links inserted after `after_id` to be posted to the channel
+    pub fn queue_channel_posts(
+        &self,
+        channel_id: i64,
+        after_id: i32,
+        queued_at: i64,
+    ) -> Result<usize> {
+        diesel::sql_query(
+            "INSERT OR IGNORE INTO channel_posts(channel_id, link_id, link, queued_at) \
+             SELECT ?, id, link, ? FROM links WHERE id > ? AND duplicate_of IS NULL",
+        )
+        .bind::<BigInt, _>(channel_id)
+        .bind::<BigInt, _>(queued_at)
+        .bind::<Integer, _>(after_id)
+        .execute(&self.connection)
+        .map_err(|e| e.into())
+    }
+
+    /// Oldest queued link that hasn't been posted to the channel yet, leaving out the ones that
+    /// failed to be posted `max_failures` times
+    pub fn next_channel_post(
+        &self,
+        channel_id: i64,
+        max_failures: i32,
+    ) -> Result<Option<model::Link>> {
+        use schema::{channel_posts, links};
+        channel_posts::table
+            .inner_join(
+                links::table.on(links::id
+                    .eq(channel_posts::link_id)
+                    .and(links::link.eq(channel_posts::link))),
+            )
+            .select(LINK_COLUMNS)
+            .filter(channel_posts::channel_id.eq(channel_id))
+            .filter(channel_posts::posted_at.is_null())
+            .filter(channel_posts::failures.lt(max_failures))
+            .order((channel_posts::queued_at, channel_posts::link_id))
+            .first(&self.connection)
+            .optional()
+            .map_err(|e| e.into())
+    }
+
+    pub fn mark_channel_post(&self, channel_id: i64, link: &str, posted_at: i64) -> Result<()> {
+        use schema::channel_posts;
+        diesel::update(
+            channel_posts::table
+                .filter(channel_posts::channel_id.eq(channel_id))
+                .filter(channel_posts::link.eq(link)),
+        )
+        .set(channel_posts::posted_at.eq(posted_at))
+        .execute(&self.connection)?;
+        Ok(())
+    }
+
+    pub fn record_channel_post_failure(&self, channel_id: i64, link: &str) -> Result<()> {
+        use schema::channel_posts;
+        diesel::update(
+            channel_posts::table
+                .filter(channel_posts::channel_id.eq(channel_id))
+                .filter(channel_posts::link.eq(link)),
+        )
+        .set(channel_posts::failures.eq(channel_posts::failures + 1))
+        .execute(&self.connection)?;
+        Ok(())
+    }
+
+    /// Remembers Telegram's id of the photo of the link, to send it again without the link
+    pub fn set_file_id(&self, link_id: i32, file_id: Option<&str>) -> Result<()> {
+        use schema::links;
+        diesel::update(links::table.filter(links::id.eq(link_id)))
+            .set(links::file_id.eq(file_id))
+            .execute(&self.connection)?;
+        Ok(())
+    }
+
+    /// Forgets the failed sends of the link's image once it was sent
+    pub fn reset_send_failures(&self, link_id: i32) -> Result<()> {
+        use schema::links;
+        diesel::update(
+            links::table
+                .filter(links::id.eq(link_id))
+                .filter(links::send_failures.gt(0)),
+        )
+        .set(links::send_failures.eq(0))
+        .execute(&self.connection)?;
+        Ok(())
+    }
+
+    /// Counts a failed send of the link's image, and quarantines the link, which keeps it out
+    /// of the searches, once it failed `max_failures` times. Returns whether it was quarantined
+    pub fn record_send_failure(&self, link_id: i32, at: i64, max_failures: i32) -> Result<bool> {
+        use schema::links;
+        diesel::update(links::table.filter(links::id.eq(link_id)))
+            .set(links::send_failures.eq(links::send_failures + 1))
+            .execute(&self.connection)?;
+        let quarantined = diesel::update(
+            links::table
+                .filter(links::id.eq(link_id))
+                .filter(links::send_failures.ge(max_failures))
+                .filter(links::quarantined_at.is_null()),
+        )
+        .set(links::quarantined_at.eq(at))
+        .execute(&self.connection)?;
+        Ok(quarantined > 0)
+    }
+
+    pub fn quarantined_links(&self) -> Result<Vec<model::Link>> {
+        use schema::links;
+        Ok(links::table
+            .select(LINK_COLUMNS)
+            .filter(links::quarantined_at.is_not_null())
+            .order(Asc::new(links::id))
+            .get_results(&self.connection)?)
+    }
+
+    /// Puts the quarantined links back in the searches, all of them or only `link_id`, with
+    /// their failures forgotten. Returns the number of links restored
+    pub fn restore_links(&self, link_id: Option<i32>) -> Result<usize> {
+        use schema::links;
+        let mut query = links::table
+            .filter(links::quarantined_at.is_not_null())
+            .into_boxed();
+        if let Some(link_id) = link_id {
+            query = query.filter(links::id.eq(link_id));
+        }
+        let ids: Vec<i32> = query.select(links::id).get_results(&self.connection)?;
+        Ok(diesel::update(links::table.filter(links::id.eq_any(ids)))
+            .set((
+                links::quarantined_at.eq(None::<i64>),
+                links::send_failures.eq(0),
+            ))
+            .execute(&self.connection)?)
+    }
+
+    /// Links inserted after `after_id` whose image isn't in the mirror
+    pub fn unmirrored_links(&self, after_id: i32) -> Result<Vec<model::Link>> {
+        use schema::{links, mirrored_images};
+        Ok(links::table
+            .select(LINK_COLUMNS)
+            .filter(links::id.gt(after_id))
+            .filter(links::quarantined_at.is_null())
+            .filter(diesel::dsl::not(links::id.eq_any(
+                mirrored_images::table.select(mirrored_images::link_id),
+            )))
+            .order(Asc::new(links::id))
+            .get_results(&self.connection)?)
+    }
+
+    pub fn insert_mirrored_image(&self, image: &model::MirroredImage) -> Result<()> {
+        use schema::mirrored_images;
+        diesel::replace_into(mirrored_images::table)
+            .values(image)
+            .execute(&self.connection)?;
+        Ok(())
+    }
+
+    pub fn mirrored_image(&self, link_id: i32) -> Result<Option<model::MirroredImage>> {
+        use schema::mirrored_images;
+        mirrored_images::table
+            .filter(mirrored_images::link_id.eq(link_id))
+            .first(&self.connection)
+            .optional()
+            .map_err(|e| e.into())
+    }
+
+    /// Path of a mirrored copy of the image, `None` if there is no file with this content yet
+    pub fn mirrored_path(&self, sha256: &str) -> Result<Option<String>> {
+        use schema::mirrored_images;
+        mirrored_images::table
+            .select(mirrored_images::path)
+            .filter(mirrored_images::sha256.eq(sha256))
+            .first(&self.connection)
+            .optional()
+            .map_err(|e| e.into())
+    }
+
+    /// Records that the image of the link was sent, which keeps its copy from being evicted
+    pub fn touch_mirrored_image(&self, link_id: i32, sent_at: i64) -> Result<()> {
+        use schema::mirrored_images;
+        diesel::update(mirrored_images::table.filter(mirrored_images::link_id.eq(link_id)))
+            .set(mirrored_images::last_sent_at.eq(sent_at))
+            .execute(&self.connection)?;
+        Ok(())
+    }
+
+    /// Records that the link is dead, its copy is then the only one left and is never evicted
+    pub fn mark_mirrored_origin_gone(&self, link_id: i32, at: i64) -> Result<()> {
+        use schema::mirrored_images;
+        diesel::update(
+            mirrored_images::table
+                .filter(mirrored_images::link_id.eq(link_id))
+                .filter(mirrored_images::origin_gone_at.is_null()),
+        )
+        .set(mirrored_images::origin_gone_at.eq(at))
+        .execute(&self.connection)?;
+        Ok(())
+    }
+
+    /// Bytes taken by the mirror, counting once the files shared by several links
+    pub fn mirror_size(&self) -> Result<i64> {
+        Ok(diesel::select(sql::<BigInt>(
+            "COALESCE((SELECT SUM(size) FROM \
+             (SELECT MAX(size) AS size FROM mirrored_images GROUP BY sha256)), 0)",
+        ))
+        .get_result(&self.connection)?)
+    }
+
+    /// The copies that can be evicted, sent least recently first
+    pub fn least_recently_sent_images(&self, limit: i64) -> Result<Vec<model::MirroredImage>> {
+        use schema::mirrored_images;
+        Ok(mirrored_images::table
+            .filter(mirrored_images::origin_gone_at.is_null())
+            .order((mirrored_images::last_sent_at, mirrored_images::link_id))
+            .limit(limit)
+            .get_results(&self.connection)?)
+    }
+
+    /// Forgets the mirrored copy of the link's image. Returns whether its file isn't used by
+    /// another link anymore
+    pub fn delete_mirrored_image(&self, link_id: i32, sha256: &str) -> Result<bool> {
+        use schema::mirrored_images;
+        diesel::delete(mirrored_images::table.filter(mirrored_images::link_id.eq(link_id)))
+            .execute(&self.connection)?;
+        let users: i64 = mirrored_images::table
+            .filter(mirrored_images::sha256.eq(sha256))
+            .count()
+            .get_result(&self.connection)?;
+        Ok(users == 0)
+    }
+
+    /// Links inserted after `after_id` whose image hasn't been hashed, out of quarantine
+    pub fn unhashed_links(&self, after_id: i32) -> Result<Vec<model::Link>> {
+        use schema::links;
+        Ok(links::table
+            .select(LINK_COLUMNS)
+            .filter(links::id.gt(after_id))
+            .filter(links::image_hash.is_null())
+            .filter(links::quarantined_at.is_null())
+            .order(Asc::new(links::id))
+            .get_results(&self.connection)?)
+    }
+
+    pub fn set_image_hash(&self, link_id: i32, image_hash: i64) -> Result<()> {
+        use schema::links;
+        diesel::update(links::table.filter(links::id.eq(link_id)))
+            .set(links::image_hash.eq(image_hash))
+            .execute(&self.connection)?;
+        Ok(())
+    }
+
+    /// Records the size of the image of the link, read from the downloaded image
+    pub fn set_image_size(
+        &self,
+        link_id: i32,
+        width: i32,
+        height: i32,
+        byte_size: i64,
+    ) -> Result<()> {
+        use schema::links;
+        diesel::update(links::table.filter(links::id.eq(link_id)))
+            .set((
+                links::width.eq(width),
+                links::height.eq(height),
+                links::byte_size.eq(byte_size),
+            ))
+            .execute(&self.connection)?;
+        Ok(())
+    }
+
+    pub fn link_score(&self, link_id: i32) -> Result<i32> {
+        use schema::links;
+        Ok(links::table
+            .select(links::score)
+            .filter(links::id.eq(link_id))
+            .first(&self.connection)?)
+    }
+
+    /// Hashed links that aren't reposts nor quarantined, to look for the copies of an image
+    /// among them
+    pub fn hashed_links(&self) -> Result<Vec<model::HashedLink>> {
+        use schema::links;
+        let hashes: Vec<(i32, Option<i64>, i32)> = links::table
+            .select((links::id, links::image_hash, links::score))
+            .filter(links::image_hash.is_not_null())
+            .filter(links::duplicate_of.is_null())
+            .filter(links::quarantined_at.is_null())
+            .order(Asc::new(links::id))
+            .get_results(&self.connection)?;
+        Ok(hashes
+            .into_iter()
+            .filter_map(|(id, image_hash, score)| {
+                image_hash.map(|image_hash| model::HashedLink {
+                    id,
+                    image_hash,
+                    score,
+                })
+            })
+            .collect())
+    }
+
+    /// Marks the links as reposts of `kept_id`, along with their own reposts
+    pub fn mark_duplicates(&self, kept_id: i32, duplicate_ids: &[i32]) -> Result<usize> {
+        use schema::links;
+        Ok(diesel::update(
+            links::table.filter(
+                links::id
+                    .eq_any(duplicate_ids)
+                    .or(links::duplicate_of.eq_any(duplicate_ids)),
+            ),
+        )
+        .set(links::duplicate_of.eq(kept_id))
+        .execute(&self.connection)?)
+    }
+
     pub fn get_all(&self, start_at_id: i32) -> Result<Vec<model::Link>> {
         use schema::links;
         Ok(links::table
-            .select((links::id, links::link, links::title))
+            .select(LINK_COLUMNS)
             .filter(links::id.ge(start_at_id))
             .order(Asc::new(links::id))
             .get_results(&self.connection)?)
@@ -122,10 +882,22 @@ impl deadpool::Manager<Database, DatabaseError> for DatabaseManager {
 pub type DbPool = deadpool::Pool<Database, DatabaseError>;
 
 #[test]
-fn test_escape_fts() {
-    assert_eq!("\"test string 132\"", escape_fts("test string 132"));
-    assert_eq!(
-        "\"test \"\"string\"\" 132\"",
-        escape_fts("test \"string\" 132")
-    );
+fn test_search_mixed_scripts() {
+    let database = Database::new(":memory:").unwrap();
+    crate::embedded_migrations::run(&database.connection).unwrap();
+    database
+        .insert_link("https://i.redd.it/a.jpg", "百合maid [artist]")
+        .unwrap();
+    database
+        .insert_link("https://i.redd.it/b.jpg", "百合 [artist]")
+        .unwrap();
+    database.index_cjk_titles().unwrap();
+    let count = |input| {
+        database
+            .count_links(&search::parse(input).unwrap())
+            .unwrap()
+    };
+    assert_eq!(1, count("百合maid"));
+    assert_eq!(1, count("合maid"));
+    assert_eq!(2, count("百合"));
 }
@@ -1,10 +1,23 @@
-use super::schema::links;
+use super::schema::{favorites, links, mirrored_images, subscriptions};
 
 #[derive(Queryable, Debug)]
 pub struct Link {
     pub id: i32,
     pub link: String,
     pub title: String,
+    pub permalink: Option<String>,
+    /// Telegram's id of the photo once it has been sent
+    pub file_id: Option<String>,
+}
+
+impl Link {
+    /// Reddit post the link was scrapped from, or the image itself for older links
+    pub fn source_url(&self) -> String {
+        match &self.permalink {
+            Some(permalink) => format!("https://www.reddit.com{}", permalink),
+            None => self.link.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Insertable)]
@@ -12,4 +25,95 @@ pub struct Link {
 pub struct NewLink<'a> {
     pub link: &'a str,
     pub title: &'a str,
+    pub score: i32,
+    pub created_utc: i64,
+    pub permalink: Option<&'a str>,
+    pub subreddit: Option<&'a str>,
+    pub media_type: Option<&'a str>,
+    pub artist: Option<&'a str>,
+    pub series: Option<&'a str>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+}
+
+/// A link saved by a user, with a copy of the link so that it survives purges
+#[derive(Queryable, Insertable, Debug)]
+#[table_name = "favorites"]
+pub struct Favorite {
+    pub user_id: i64,
+    pub link_id: i32,
+    pub link: String,
+    pub title: String,
+    pub added_at: i64,
+}
+
+/// A copy of the image of a link in the mirror directory
+#[derive(Queryable, Insertable, Debug)]
+#[table_name = "mirrored_images"]
+pub struct MirroredImage {
+    pub link_id: i32,
+    pub sha256: String,
+    pub path: String,
+    pub size: i64,
+    pub mime_type: String,
+    pub mirrored_at: i64,
+    /// Last time the link was sent, the images sent least recently are evicted first
+    pub last_sent_at: i64,
+    /// When the link was found dead, its copy is then never evicted
+    pub origin_gone_at: Option<i64>,
+}
+
+/// Perceptual hash of the image of a link, see `images::perceptual_hash`
+#[derive(Debug, Clone, Copy)]
+pub struct HashedLink {
+    pub id: i32,
+    pub image_hash: i64,
+    pub score: i32,
+}
+
+#[derive(Queryable, Debug, Clone, Copy)]
+pub struct Candidate {
+    pub id: i32,
+    pub score: i32,
+    pub created_utc: i64,
+}
+
+#[derive(Queryable, Debug)]
+pub struct ChatSettings {
+    pub chat_id: i64,
+    pub selection_mode: String,
+    pub max_album_size: i32,
+    /// Minutes to add to UTC to get the chat's time
+    pub utc_offset: i32,
+    /// Language of the replies, `None` to use the language of each user
+    pub language: Option<String>,
+    /// Commands the chat can send each minute, `None` for the configured limit
+    pub commands_per_minute: Option<i32>,
+    /// Smallest width and height of the images sent to the chat, `None` for any size
+    pub min_resolution: Option<i32>,
+}
+
+impl ChatSettings {
+    pub fn default_for(chat_id: i64) -> Self {
+        ChatSettings {
+            chat_id,
+            selection_mode: "uniform".to_owned(),
+            max_album_size: 5,
+            utc_offset: 0,
+            language: None,
+            commands_per_minute: None,
+            min_resolution: None,
+        }
+    }
+}
+
+/// A daily image sent to a chat
+#[derive(Queryable, Insertable, Debug)]
+#[table_name = "subscriptions"]
+pub struct Subscription {
+    pub chat_id: i64,
+    /// Minutes since midnight, in the chat's time
+    pub time_of_day: i32,
+    pub query: String,
+    pub next_at: i64,
 }
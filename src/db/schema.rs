@@ -1,8 +1,65 @@
+table! {
+    channel_posts (channel_id, link) {
+        channel_id -> BigInt,
+        link_id -> Integer,
+        link -> Text,
+        queued_at -> BigInt,
+        posted_at -> Nullable<BigInt>,
+        failures -> Integer,
+    }
+}
+
+table! {
+    chat_settings (chat_id) {
+        chat_id -> BigInt,
+        selection_mode -> Text,
+        max_album_size -> Integer,
+        utc_offset -> Integer,
+        language -> Nullable<Text>,
+        commands_per_minute -> Nullable<Integer>,
+        min_resolution -> Nullable<Integer>,
+    }
+}
+
+table! {
+    favorites (user_id, link_id) {
+        user_id -> BigInt,
+        link_id -> Integer,
+        link -> Text,
+        title -> Text,
+        added_at -> BigInt,
+    }
+}
+
 table! {
     links (id) {
         id -> Integer,
         link -> Text,
         title -> Text,
+        score -> Integer,
+        created_utc -> BigInt,
+        permalink -> Nullable<Text>,
+        subreddit -> Nullable<Text>,
+        media_type -> Nullable<Text>,
+        artist -> Nullable<Text>,
+        series -> Nullable<Text>,
+        file_id -> Nullable<Text>,
+        quarantined_at -> Nullable<BigInt>,
+        send_failures -> Integer,
+        image_hash -> Nullable<BigInt>,
+        duplicate_of -> Nullable<Integer>,
+        width -> Nullable<Integer>,
+        height -> Nullable<Integer>,
+        byte_size -> Nullable<BigInt>,
+    }
+}
+
+table! {
+    links_cjk_idx (rowid) {
+        rowid -> Integer,
+        grams -> Text,
+        #[sql_name = "links_cjk_idx"]
+        whole_row -> Text,
     }
 }
 
@@ -15,3 +72,56 @@ table! {
         whole_row -> Text,
     }
 }
+
+table! {
+    links_title_vocab (term) {
+        term -> Text,
+        doc -> Integer,
+        cnt -> Integer,
+    }
+}
+
+table! {
+    mirrored_images (link_id) {
+        link_id -> Integer,
+        sha256 -> Text,
+        path -> Text,
+        size -> BigInt,
+        mime_type -> Text,
+        mirrored_at -> BigInt,
+        last_sent_at -> BigInt,
+        origin_gone_at -> Nullable<BigInt>,
+    }
+}
+
+table! {
+    subscriptions (chat_id) {
+        chat_id -> BigInt,
+        time_of_day -> Integer,
+        query -> Text,
+        next_at -> BigInt,
+    }
+}
+
+table! {
+    votes (link_id, user_id) {
+        link_id -> Integer,
+        user_id -> BigInt,
+        value -> Integer,
+        voted_at -> BigInt,
+    }
+}
+
+joinable!(channel_posts -> links (link_id));
+joinable!(favorites -> links (link_id));
+joinable!(mirrored_images -> links (link_id));
+
+allow_tables_to_appear_in_same_query!(
+    channel_posts,
+    favorites,
+    links,
+    links_cjk_idx,
+    links_title_idx,
+    mirrored_images,
+    votes
+);
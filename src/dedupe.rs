@@ -0,0 +1,167 @@
+//! Detection of the reposts of an image under another link or title, by comparing the
+//! perceptual hashes of the images
+
+use crate::db::{
+    self,
+    model::{HashedLink, Link},
+};
+use crate::images;
+use crate::{Result, YuribotError};
+
+use std::cmp::Reverse;
+use std::time::Duration;
+
+/// Groups the links whose images are within `max_distance` of the best scored one of the group,
+/// which comes first and is the copy kept. Links without copies aren't returned
+fn clusters(links: &[HashedLink], max_distance: u32) -> Vec<Vec<i32>> {
+    let mut links = links.to_vec();
+    // The oldest link is kept among equal scores
+    links.sort_by_key(|link| (Reverse(link.score), link.id));
+    let mut clustered = vec![false; links.len()];
+    let mut clusters = Vec::new();
+    for (i, kept) in links.iter().enumerate() {
+        if clustered[i] {
+            continue;
+        }
+        let mut cluster = vec![kept.id];
+        for (j, link) in links.iter().enumerate().skip(i + 1) {
+            if !clustered[j]
+                && images::hash_distance(kept.image_hash, link.image_hash) <= max_distance
+            {
+                clustered[j] = true;
+                cluster.push(link.id);
+            }
+        }
+        if cluster.len() > 1 {
+            clusters.push(cluster);
+        }
+    }
+    clusters
+}
+
+/// Hashes the image of the link, read from the mirror when it has one, and records its size
+/// unless it is a dry run
+async fn hash_image(database: &db::Database, link: &Link, dry_run: bool) -> Result<i64> {
+    let data = match database.mirrored_image(link.id)? {
+        Some(image) => images::read_image(&image.path).await?,
+        None => images::download_image(&link.link, Duration::from_secs(30)).await?,
+    };
+    let (width, height) = images::image_size(&data)?;
+    if !dry_run {
+        database.set_image_size(link.id, width as i32, height as i32, data.len() as i64)?;
+    }
+    Ok(images::perceptual_hash(data).await?)
+}
+
+/// Hashes the images of the links, the ones that can't be fetched are left unhashed. The
+/// hashes are only recorded when it isn't a dry run, they are returned by link id either way
+async fn hash_links(
+    database: &db::Database,
+    links: &[Link],
+    dry_run: bool,
+) -> Result<Vec<(i32, i64)>> {
+    let mut hashed = Vec::with_capacity(links.len());
+    for (i, link) in links.iter().enumerate() {
+        if i != 0 && i % 100 == 0 {
+            info!("hashed {}/{} images", i, links.len());
+        }
+        match hash_image(database, link, dry_run).await {
+            Ok(image_hash) => {
+                if !dry_run {
+                    database.set_image_hash(link.id, image_hash)?;
+                }
+                hashed.push((link.id, image_hash));
+            }
+            Err(YuribotError::DatabaseError(e)) => return Err(e.into()),
+            Err(e) => warn!("failed to hash the image of {}: {}", link.link, e),
+        }
+    }
+    Ok(hashed)
+}
+
+/// Hashes the images of the links inserted after `after_id`, and marks the ones reposting an
+/// image as duplicates of its best scored copy, which can be the new one
+pub async fn dedupe_new_links(
+    database: &db::Database,
+    after_id: i32,
+    max_distance: u32,
+) -> Result<()> {
+    hash_links(database, &database.unhashed_links(after_id)?, false).await?;
+    let hashed = database.hashed_links()?;
+    let new_links: Vec<&HashedLink> = hashed.iter().filter(|link| link.id > after_id).collect();
+    // Only the copies of the new images are clustered, comparing every image is left to --dedupe
+    let copies: Vec<HashedLink> = hashed
+        .iter()
+        .filter(|link| {
+            new_links.iter().any(|new_link| {
+                images::hash_distance(new_link.image_hash, link.image_hash) <= max_distance
+            })
+        })
+        .copied()
+        .collect();
+    for cluster in clusters(&copies, max_distance) {
+        info!(
+            "marking links {:?} as reposts of {}",
+            &cluster[1..],
+            cluster[0]
+        );
+        database.mark_duplicates(cluster[0], &cluster[1..])?;
+    }
+    Ok(())
+}
+
+/// Hashes the images that weren't, then marks the reposts among all the links as duplicates of
+/// the best scored copy of their image
+pub async fn dedupe(db_pool: db::DbPool, dry_run: bool, max_distance: u32) -> Result<()> {
+    let database = db_pool.get().await?;
+    let links = database.unhashed_links(0)?;
+    info!("hashing {} images", links.len());
+    let new_hashes = hash_links(&database, &links, dry_run).await?;
+    let mut hashed = database.hashed_links()?;
+    if dry_run {
+        for (id, image_hash) in new_hashes {
+            hashed.push(HashedLink {
+                id,
+                image_hash,
+                score: database.link_score(id)?,
+            });
+        }
+    }
+    let clusters = clusters(&hashed, max_distance);
+    let mut duplicate_count = 0;
+    for cluster in &clusters {
+        info!(
+            "keeping link {} over its reposts {:?}",
+            cluster[0],
+            &cluster[1..]
+        );
+        duplicate_count += cluster.len() - 1;
+        if !dry_run {
+            database.mark_duplicates(cluster[0], &cluster[1..])?;
+        }
+    }
+    info!(
+        "marked {} reposts of {} images",
+        duplicate_count,
+        clusters.len()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_clusters() {
+    let link = |id, image_hash, score| HashedLink {
+        id,
+        image_hash,
+        score,
+    };
+    let links = [
+        link(1, 0b0000, 10),
+        link(2, 0b0001, 50),
+        link(3, 0b1111_0000, 20),
+        link(4, 0b0011, 50),
+        link(5, 0b1111_0001, 1),
+    ];
+    assert_eq!(vec![vec![2, 4, 1], vec![3, 5]], clusters(&links, 1));
+    assert_eq!(Vec::<Vec<i32>>::new(), clusters(&links, 0));
+}
@@ -1,4 +1,6 @@
 use crate::db;
+use crate::images;
+use crate::mirror;
 use crate::purge_links;
 use crate::reddit_api;
 
@@ -20,8 +22,18 @@ pub enum YuribotError {
     MigrationError(#[from] diesel_migrations::RunMigrationsError),
     #[error("no telegram bot token has been provided. Provide one either as an env variable YURIBOT_BOT_TOKEN, or as a variable in the Yuribot.toml")]
     NoTelegramTokenError,
-    #[error("Unable to parse the command passed to the bot")]
-    CommandArgParseError,
+    #[error("invalid webhook_listen address: {0}")]
+    WebhookAddressError(#[from] std::net::AddrParseError),
+    #[error("webhook server error: {0}")]
+    WebhookServerError(#[from] hyper::Error),
+    #[error("invalid command config: {0}")]
+    CommandConfigError(String),
+    #[error("error while fetching an image: {0}")]
+    ImageFetchError(#[from] images::FetchError),
+    #[error("the image of link {0} can't be sent")]
+    UnavailableImageError(i32),
+    #[error("error while mirroring the images: {0}")]
+    MirrorError(#[from] mirror::MirrorError),
     #[error("error while purging the database {0}")]
     PurgeError(#[from] purge_links::PurgeError),
 }
@@ -0,0 +1,266 @@
+//! Download of the images, to upload them to Telegram when it can't fetch them itself, and to
+//! keep them in the mirror
+
+use std::convert::TryFrom;
+use std::io::Cursor;
+use std::time::Duration;
+
+use hyper::{body::HttpBody, client::HttpConnector, header, Body, Client, Request, Uri};
+use hyper_tls::HttpsConnector;
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, ImageFormat};
+use thiserror::Error;
+use tokio::time;
+
+/// Largest photo Telegram accepts
+const MAX_PHOTO_SIZE: usize = 10 * 1024 * 1024;
+/// Telegram also wants the width and height of a photo to add up to at most this
+const MAX_PHOTO_DIMENSIONS: u32 = 10_000;
+/// Downloads are given up past this size
+const MAX_DOWNLOAD_SIZE: usize = 50 * 1024 * 1024;
+const MAX_REDIRECTS: u32 = 5;
+const JPEG_QUALITY: u8 = 85;
+/// Number of times a photo too large after compression is scaled down
+const MAX_DOWNSCALES: u32 = 4;
+
+#[derive(Debug, Error)]
+pub enum FetchError {
+    #[error("invalid image uri: {0}")]
+    InvalidUri(#[from] hyper::http::uri::InvalidUri),
+    #[error("invalid image uri: {0}")]
+    InvalidUriParts(#[from] hyper::http::uri::InvalidUriParts),
+    #[error("error while downloading the image: {0}")]
+    Download(#[from] hyper::Error),
+    #[error("received status code {0} downloading the image")]
+    UnexpectedStatusCode(u16),
+    #[error("too many redirects downloading the image")]
+    TooManyRedirects,
+    #[error("timed out downloading the image")]
+    Timeout,
+    #[error("the image is too large")]
+    TooLarge,
+    #[error("error while converting the image: {0}")]
+    Convert(#[from] image::ImageError),
+    #[error("error while reading the image: {0}")]
+    Read(#[from] std::io::Error),
+}
+
+impl FetchError {
+    /// Whether the image itself can't be had, rather than its host or the network failing for
+    /// a while
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            FetchError::InvalidUri(_)
+            | FetchError::InvalidUriParts(_)
+            | FetchError::TooManyRedirects
+            | FetchError::TooLarge
+            | FetchError::Convert(_) => true,
+            FetchError::UnexpectedStatusCode(status) => {
+                (400..500).contains(status) && *status != 408 && *status != 429
+            }
+            FetchError::Download(_) | FetchError::Timeout | FetchError::Read(_) => false,
+        }
+    }
+}
+
+/// A photo ready to be uploaded to Telegram
+pub struct Photo {
+    pub data: Vec<u8>,
+    pub file_name: &'static str,
+}
+
+/// Downloads the image and makes a photo Telegram accepts of it
+pub async fn fetch_photo(url: &str, timeout: Duration) -> Result<Photo, FetchError> {
+    to_photo(download_image(url, timeout).await?).await
+}
+
+/// Reads a mirrored image and makes a photo Telegram accepts of it
+pub async fn read_photo(path: &str) -> Result<Photo, FetchError> {
+    to_photo(read_image(path).await?).await
+}
+
+pub async fn read_image(path: &str) -> Result<Vec<u8>, FetchError> {
+    Ok(tokio::fs::read(path).await?)
+}
+
+async fn to_photo(data: Vec<u8>) -> Result<Photo, FetchError> {
+    // Decoding and encoding the image is too slow for the runtime's threads
+    tokio::task::spawn_blocking(move || fit_photo(data))
+        .await
+        .expect("the conversion of the image panicked")
+}
+
+pub async fn download_image(url: &str, timeout: Duration) -> Result<Vec<u8>, FetchError> {
+    time::timeout(timeout, download(url))
+        .await
+        .map_err(|_| FetchError::Timeout)?
+}
+
+/// MIME type of the image, `None` if the data isn't an image Telegram can be sent
+pub fn mime_type(data: &[u8]) -> Option<&'static str> {
+    match image::guess_format(data).ok()? {
+        ImageFormat::Jpeg => Some("image/jpeg"),
+        ImageFormat::Png => Some("image/png"),
+        ImageFormat::Gif => Some("image/gif"),
+        ImageFormat::WebP => Some("image/webp"),
+        _ => None,
+    }
+}
+
+async fn download(url: &str) -> Result<Vec<u8>, FetchError> {
+    let client: Client<HttpsConnector<HttpConnector>> =
+        Client::builder().build(HttpsConnector::new());
+    let mut uri = Uri::try_from(url)?;
+    for _ in 0..MAX_REDIRECTS {
+        let request = Request::get(uri.clone())
+            .header(header::USER_AGENT, format!("yuribot_rs/{}", crate::VERSION))
+            .body(Body::empty())
+            .expect("couldn't build the image request");
+        let mut response = client.request(request).await?;
+        let status = response.status();
+        if status.is_redirection() {
+            if let Some(next) = response.headers().get(header::LOCATION) {
+                let next = Uri::try_from(next.as_bytes())?;
+                uri = if next.authority().is_none() {
+                    let mut parts = uri.into_parts();
+                    parts.path_and_query = next.into_parts().path_and_query;
+                    Uri::from_parts(parts)?
+                } else {
+                    next
+                };
+                continue;
+            }
+        }
+        if !status.is_success() {
+            return Err(FetchError::UnexpectedStatusCode(status.as_u16()));
+        }
+        let mut data = Vec::new();
+        while let Some(chunk) = response.body_mut().data().await {
+            let chunk = chunk?;
+            if data.len() + chunk.len() > MAX_DOWNLOAD_SIZE {
+                return Err(FetchError::TooLarge);
+            }
+            data.extend_from_slice(&chunk);
+        }
+        return Ok(data);
+    }
+    Err(FetchError::TooManyRedirects)
+}
+
+/// The image itself if it is a JPEG or PNG within Telegram's limits, or else the image
+/// recompressed to JPEG and scaled down until it is
+fn fit_photo(data: Vec<u8>) -> Result<Photo, FetchError> {
+    let format = image::guess_format(&data)?;
+    let mut image = image::load_from_memory(&data)?;
+    let dimensions = image.width() + image.height();
+    if data.len() <= MAX_PHOTO_SIZE && dimensions <= MAX_PHOTO_DIMENSIONS {
+        match format {
+            ImageFormat::Jpeg => {
+                return Ok(Photo {
+                    data,
+                    file_name: "photo.jpg",
+                })
+            }
+            ImageFormat::Png => {
+                return Ok(Photo {
+                    data,
+                    file_name: "photo.png",
+                })
+            }
+            _ => {}
+        }
+    }
+    if dimensions > MAX_PHOTO_DIMENSIONS {
+        image = scale(
+            &image,
+            f64::from(MAX_PHOTO_DIMENSIONS) / f64::from(dimensions),
+        );
+    }
+    for _ in 0..=MAX_DOWNSCALES {
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY).encode_image(&image.to_rgb8())?;
+        if jpeg.len() <= MAX_PHOTO_SIZE {
+            return Ok(Photo {
+                data: jpeg,
+                file_name: "photo.jpg",
+            });
+        }
+        image = scale(&image, 0.75);
+    }
+    Err(FetchError::TooLarge)
+}
+
+/// Width and height of the image, read from its header
+pub fn image_size(data: &[u8]) -> Result<(u32, u32), FetchError> {
+    Ok(image::io::Reader::new(Cursor::new(data))
+        .with_guessed_format()?
+        .into_dimensions()?)
+}
+
+/// Perceptual hash of the image, which differs by a few bits between copies of an image that
+/// were resized or recompressed
+pub async fn perceptual_hash(data: Vec<u8>) -> Result<i64, FetchError> {
+    tokio::task::spawn_blocking(move || {
+        let image = image::load_from_memory(&data)?;
+        let pixels = image
+            .resize_exact(HASH_WIDTH + 1, HASH_HEIGHT, FilterType::Triangle)
+            .to_luma8()
+            .into_raw();
+        Ok(difference_hash(&pixels))
+    })
+    .await
+    .expect("the hashing of the image panicked")
+}
+
+const HASH_WIDTH: u32 = 8;
+const HASH_HEIGHT: u32 = 8;
+
+/// dHash of a 9x8 grayscale image: each bit tells whether a pixel is brighter than the next
+/// one on its row
+fn difference_hash(pixels: &[u8]) -> i64 {
+    let mut hash = 0_u64;
+    for row in pixels.chunks((HASH_WIDTH + 1) as usize) {
+        for pair in row.windows(2) {
+            hash = (hash << 1) | u64::from(pair[0] > pair[1]);
+        }
+    }
+    hash as i64
+}
+
+/// Number of bits that differ between two perceptual hashes
+pub fn hash_distance(a: i64, b: i64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+fn scale(image: &DynamicImage, factor: f64) -> DynamicImage {
+    let scaled = |size: u32| ((f64::from(size) * factor) as u32).max(1);
+    image.resize(
+        scaled(image.width()),
+        scaled(image.height()),
+        FilterType::Lanczos3,
+    )
+}
+
+#[test]
+fn test_difference_hash() {
+    let gradient: Vec<u8> = (0..8).flat_map(|_| (0..9).map(|x| 200 - x * 10)).collect();
+    assert_eq!(-1, difference_hash(&gradient));
+    let mut brighter = gradient.iter().map(|pixel| pixel + 20).collect::<Vec<u8>>();
+    assert_eq!(
+        0,
+        hash_distance(difference_hash(&gradient), difference_hash(&brighter))
+    );
+    brighter[0] = 0;
+    assert_eq!(
+        1,
+        hash_distance(difference_hash(&gradient), difference_hash(&brighter))
+    );
+}
+
+#[test]
+fn test_fetch_error_is_permanent() {
+    assert!(FetchError::UnexpectedStatusCode(404).is_permanent());
+    assert!(!FetchError::UnexpectedStatusCode(429).is_permanent());
+    assert!(!FetchError::UnexpectedStatusCode(503).is_permanent());
+    assert!(!FetchError::Timeout.is_permanent());
+    assert!(FetchError::TooLarge.is_permanent());
+}
@@ -1,12 +1,19 @@
 mod bot;
 mod db;
+mod dedupe;
 mod errors;
+mod images;
+mod mirror;
 mod parse_args;
 mod purge_links;
+mod rate_limit;
 mod reddit_api;
 mod scrapper;
+mod search;
+mod selection;
 mod utils;
 
+use std::collections::HashMap;
 use std::time::Duration;
 
 #[macro_use]
@@ -31,6 +38,33 @@ pub struct Config {
     bot_token: Option<String>,
     reddit_user_agent: String,
     log: String,
+    score_exponent: f64,
+    vote_weight: f64,
+    fresh_half_life_days: f64,
+    inline_cache_time: i64,
+    inline_queries_per_minute: u32,
+    commands_per_user_per_minute: u32,
+    commands_per_chat_per_minute: u32,
+    channel_ids: Vec<i64>,
+    channel_post_interval: u64,
+    channel_hashtags: Vec<String>,
+    webhook_url: Option<String>,
+    webhook_listen: String,
+    webhook_secret_token: Option<String>,
+    mirror_dir: Option<String>,
+    mirror_max_megabytes: i64,
+    duplicate_max_distance: u32,
+    commands: HashMap<String, bot::CommandNames>,
+}
+
+/// Secret token for the webhook when none is configured, it only has to last until the bot stops
+fn random_secret_token() -> String {
+    use rand::{distributions::Alphanumeric, Rng};
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
 }
 
 fn read_config(path: &str) -> Result<Config> {
@@ -38,6 +72,20 @@ fn read_config(path: &str) -> Result<Config> {
         .set_default("database_path", "yuribot_rs.sqlite3")?
         .set_default("log", "yuribot_rs=info")?
         .set_default("reddit_user_agent", format!("yuribot_rs/{}", VERSION))?
+        .set_default("score_exponent", 1.0)?
+        .set_default("vote_weight", 100.0)?
+        .set_default("fresh_half_life_days", 30.0)?
+        .set_default("inline_cache_time", 300)?
+        .set_default("inline_queries_per_minute", 30)?
+        .set_default("commands_per_user_per_minute", 10)?
+        .set_default("commands_per_chat_per_minute", 20)?
+        .set_default("channel_ids", Vec::<i64>::new())?
+        .set_default("channel_post_interval", 300)?
+        .set_default("channel_hashtags", Vec::<String>::new())?
+        .set_default("webhook_listen", "127.0.0.1:8080")?
+        .set_default("mirror_max_megabytes", 1024)?
+        .set_default("duplicate_max_distance", 6)?
+        .set_default("commands", config::Map::<String, config::Value>::new())?
         .add_source(config::File::with_name(path).required(false))
         .add_source(config::Environment::with_prefix("YURIBOT"))
         .build()?;
@@ -72,25 +120,87 @@ async fn inner_main() -> Result<()> {
     );
     info!("running migrations");
     embedded_migrations::run(&db_pool.get().await?.connection)?;
+    let indexed = db_pool.get().await?.index_cjk_titles()?;
+    if indexed > 0 {
+        info!("indexed {} titles for CJK searches", indexed);
+    }
+
+    let mirror = conf
+        .mirror_dir
+        .as_ref()
+        .map(|dir| mirror::Mirror::new(dir, conf.mirror_max_megabytes * 1024 * 1024));
 
     use parse_args::Action::*;
     match action {
         RunBot => {
-            let bot_api = telegram_bot::Api::new(
+            let outbox = bot::Outbox::new(telegram_bot::Api::new(
                 conf.bot_token
                     .as_ref()
                     .ok_or(YuribotError::NoTelegramTokenError)?,
-            );
-            let bot_task = bot::start_bot(db_pool.clone(), bot_api).fuse();
-            let scrapper_task = scrapper::run_scrapper(db_pool.clone(), rd_pool).fuse();
-            pin_mut!(bot_task, scrapper_task);
+            ));
+            let bot_config = bot::BotConfig {
+                weights: selection::Weights {
+                    score_exponent: conf.score_exponent,
+                    vote_weight: conf.vote_weight,
+                    fresh_half_life_days: conf.fresh_half_life_days,
+                },
+                inline_cache_time: conf.inline_cache_time,
+                inline_queries_per_minute: conf.inline_queries_per_minute,
+                commands_per_user_per_minute: conf.commands_per_user_per_minute,
+                commands_per_chat_per_minute: conf.commands_per_chat_per_minute,
+                webhook: match &conf.webhook_url {
+                    Some(url) => Some(bot::WebhookConfig {
+                        url: url.clone(),
+                        listen: conf.webhook_listen.parse()?,
+                        secret_token: conf
+                            .webhook_secret_token
+                            .clone()
+                            .unwrap_or_else(random_secret_token),
+                    }),
+                    None => None,
+                },
+                commands: bot::Registry::new(&conf.commands)
+                    .map_err(YuribotError::CommandConfigError)?,
+            };
+            let scheduler_task =
+                bot::run_scheduler(db_pool.clone(), outbox.clone(), bot_config.weights).fuse();
+            let channel_task = bot::run_channel_poster(
+                db_pool.clone(),
+                outbox.clone(),
+                bot::ChannelConfig {
+                    channel_ids: conf.channel_ids.clone(),
+                    post_interval: Duration::from_secs(conf.channel_post_interval),
+                    hashtags: conf.channel_hashtags.clone(),
+                },
+            )
+            .fuse();
+            let bot_task = bot::start_bot(db_pool.clone(), outbox, bot_config).fuse();
+            let scrapper_task = scrapper::run_scrapper(
+                db_pool.clone(),
+                rd_pool,
+                conf.channel_ids.clone(),
+                mirror,
+                conf.duplicate_max_distance,
+            )
+            .fuse();
+            pin_mut!(bot_task, scrapper_task, scheduler_task, channel_task);
             select!(
                 _ = bot_task => (),
                 _ = scrapper_task => (),
+                _ = scheduler_task => (),
+                _ = channel_task => (),
             )
         }
-        SeedDatabase { limit } => scrapper::seed_database(limit, rd_pool, db_pool).await?,
+        SeedDatabase { limit } => {
+            scrapper::seed_database(limit, rd_pool, db_pool, mirror, conf.duplicate_max_distance)
+                .await?
+        }
         PurgeLinks { dry_run, start_at_id } => purge_links::purge_links(db_pool, dry_run, start_at_id).await?,
+        ConfirmQuarantine { dry_run, link_id } => {
+            purge_links::confirm_quarantine(db_pool, dry_run, link_id).await?
+        }
+        RestoreQuarantine { link_id } => purge_links::restore_quarantine(db_pool, link_id).await?,
+        Dedupe { dry_run } => dedupe::dedupe(db_pool, dry_run, conf.duplicate_max_distance).await?,
         Help(_) => unreachable!(),
     };
 
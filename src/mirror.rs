@@ -0,0 +1,139 @@
+//! Copies of the scraped images, named by the SHA-256 of their content, to upload them to
+//! Telegram once their host deleted them
+
+use crate::db::{self, model::MirroredImage};
+use crate::images;
+use crate::utils::unix_now;
+
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+/// Number of copies looked at each time the least recently sent ones are evicted
+const EVICTION_BATCH: i64 = 100;
+
+#[derive(Debug, Error)]
+pub enum MirrorError {
+    #[error("error while querying the database: {0}")]
+    Database(#[from] db::errors::DatabaseError),
+    #[error("error while downloading the image: {0}")]
+    Download(#[from] images::FetchError),
+    #[error("the downloaded file isn't an image")]
+    NotAnImage,
+    #[error("error while writing to the mirror: {0}")]
+    Write(#[from] io::Error),
+}
+
+pub struct Mirror {
+    dir: PathBuf,
+    /// Bytes the copies can take, past which the ones sent least recently are evicted
+    max_size: i64,
+}
+
+impl Mirror {
+    pub fn new(dir: &str, max_size: i64) -> Self {
+        Mirror {
+            dir: PathBuf::from(dir),
+            max_size,
+        }
+    }
+
+    /// Copies the images of the links inserted after `after_id`, then evicts the images sent
+    /// least recently until the mirror fits in its size, except the ones whose host deleted them
+    pub async fn mirror_links(
+        &self,
+        database: &db::Database,
+        after_id: i32,
+    ) -> Result<(), MirrorError> {
+        let links = database.unmirrored_links(after_id)?;
+        let mut mirrored_count = 0;
+        for link in &links {
+            match self.mirror_link(database, link.id, &link.link).await {
+                Ok(()) => mirrored_count += 1,
+                Err(MirrorError::Database(e)) => return Err(e.into()),
+                Err(e) => warn!("failed to mirror {}: {}", link.link, e),
+            }
+        }
+        if !links.is_empty() {
+            info!("mirrored {} of {} new links", mirrored_count, links.len());
+        }
+        self.evict(database).await
+    }
+
+    async fn mirror_link(
+        &self,
+        database: &db::Database,
+        link_id: i32,
+        url: &str,
+    ) -> Result<(), MirrorError> {
+        let data = images::download_image(url, Duration::from_secs(30)).await?;
+        let mime_type = images::mime_type(&data).ok_or(MirrorError::NotAnImage)?;
+        let (width, height) = images::image_size(&data)?;
+        database.set_image_size(link_id, width as i32, height as i32, data.len() as i64)?;
+        let sha256 = format!("{:x}", Sha256::digest(&data));
+        let path = match database.mirrored_path(&sha256)? {
+            Some(path) => path,
+            None => {
+                let dir = self.dir.join(&sha256[..2]);
+                tokio::fs::create_dir_all(&dir).await?;
+                let path = dir.join(&sha256);
+                // Written aside first, so that a copy is never left half written
+                let partial = dir.join(format!("{}.part", sha256));
+                tokio::fs::write(&partial, &data).await?;
+                tokio::fs::rename(&partial, &path).await?;
+                path.to_string_lossy().into_owned()
+            }
+        };
+        let now = unix_now();
+        database.insert_mirrored_image(&MirroredImage {
+            link_id,
+            sha256,
+            path,
+            size: data.len() as i64,
+            mime_type: mime_type.to_owned(),
+            mirrored_at: now,
+            last_sent_at: now,
+            origin_gone_at: None,
+        })?;
+        Ok(())
+    }
+
+    async fn evict(&self, database: &db::Database) -> Result<(), MirrorError> {
+        let mut size = database.mirror_size()?;
+        let mut evicted_count = 0;
+        while size > self.max_size {
+            let images = database.least_recently_sent_images(EVICTION_BATCH)?;
+            if images.is_empty() {
+                break;
+            }
+            for image in &images {
+                if size <= self.max_size {
+                    break;
+                }
+                if forget(database, image).await? {
+                    size -= image.size;
+                }
+                evicted_count += 1;
+            }
+        }
+        if evicted_count > 0 {
+            info!("evicted {} images from the mirror", evicted_count);
+        }
+        Ok(())
+    }
+}
+
+/// Forgets the copy of the image of a link, deleting its file when no other link uses it.
+/// Returns whether the file was deleted
+pub async fn forget(database: &db::Database, image: &MirroredImage) -> Result<bool, MirrorError> {
+    if !database.delete_mirrored_image(image.link_id, &image.sha256)? {
+        return Ok(false);
+    }
+    match tokio::fs::remove_file(&image.path).await {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(true),
+    }
+}
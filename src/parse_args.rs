@@ -4,6 +4,12 @@ pub enum Action {
     SeedDatabase { limit: usize },
     RunBot,
     PurgeLinks { dry_run: bool, start_at_id: usize },
+    /// deletes the quarantined links, all of them or only one
+    ConfirmQuarantine { dry_run: bool, link_id: Option<i32> },
+    /// puts the quarantined links back, all of them or only one
+    RestoreQuarantine { link_id: Option<i32> },
+    /// marks the reposts of an image as duplicates of its best scored copy
+    Dedupe { dry_run: bool },
     Help(String),
 }
 
@@ -35,6 +41,30 @@ pub fn parse_args(args: Vec<String>) -> Action {
             getopts::HasArg::Yes,
             getopts::Occur::Optional
         );
+        opts.opt(
+            "",
+            "confirm",
+            "combine with --purge to delete the links quarantined because their image couldn't be sent, or only the one with the id, instead of checking every link",
+            "ID",
+            getopts::HasArg::Maybe,
+            getopts::Occur::Optional,
+        );
+        opts.opt(
+            "",
+            "restore",
+            "combine with --purge to put the quarantined links back, or only the one with the id",
+            "ID",
+            getopts::HasArg::Maybe,
+            getopts::Occur::Optional,
+        );
+        opts.opt(
+            "",
+            "dedupe",
+            "hashes the images that weren't, then hides the reposts of each image behind its best scored copy. With --dry_run, only logs them",
+            "",
+            getopts::HasArg::No,
+            getopts::Occur::Optional,
+        );
         opts.opt(
             "s",
             "seed",
@@ -59,6 +89,26 @@ pub fn parse_args(args: Vec<String>) -> Action {
             Err(_) => Help(opts.usage("failed to parse --seed argument to integer")),
         };
     }
+    if matches.opt_present("dedupe") {
+        return Dedupe {
+            dry_run: matches.opt_present("dry_run"),
+        };
+    }
+    if matches.opt_present("purge") && matches.opt_present("confirm") {
+        return match matches.opt_get::<i32>("confirm") {
+            Ok(link_id) => ConfirmQuarantine {
+                dry_run: matches.opt_present("dry_run"),
+                link_id,
+            },
+            Err(_) => Help(opts.usage("failed to parse --confirm argument to integer")),
+        };
+    }
+    if matches.opt_present("purge") && matches.opt_present("restore") {
+        return match matches.opt_get::<i32>("restore") {
+            Ok(link_id) => RestoreQuarantine { link_id },
+            Err(_) => Help(opts.usage("failed to parse --restore argument to integer")),
+        };
+    }
     if matches.opt_present("purge") {
         return PurgeLinks {
             dry_run: matches.opt_present("dry_run"),
@@ -1,4 +1,6 @@
 use crate::db::{self, model};
+use crate::mirror;
+use crate::utils::unix_now;
 use std::convert::{TryFrom, TryInto};
 
 use thiserror::Error;
@@ -23,6 +25,8 @@ pub(crate) enum PurgeError {
     UnexpectedStatusCode(u16, String, i32),
     #[error("failed to cast start_id to i32")]
     InvalidStartIdValue(#[from] std::num::TryFromIntError),
+    #[error("error while removing a mirrored image: {0}")]
+    Mirror(#[from] mirror::MirrorError),
 }
 
 pub async fn purge_links(
@@ -62,6 +66,18 @@ pub async fn purge_links(
             }
         };
         if !found {
+            // The mirror still has the image, it can be uploaded from there
+            if db.mirrored_image(link.id)?.is_some() {
+                log::info!(
+                    "keeping link {}, its image is mirrored: {}",
+                    link.id,
+                    link.link
+                );
+                if !dry_run {
+                    db.mark_mirrored_origin_gone(link.id, unix_now())?;
+                }
+                continue;
+            }
             removed_count += 1;
             if !dry_run {
                 db.delete(link.id)
@@ -73,6 +89,43 @@ pub async fn purge_links(
     Ok(())
 }
 
+/// Deletes the links quarantined because their image couldn't be sent, or only `link_id`
+pub async fn confirm_quarantine(
+    db_pool: db::DbPool,
+    dry_run: bool,
+    link_id: Option<i32>,
+) -> Result<(), PurgeError> {
+    let db = db_pool.get().await?;
+    let links = db.quarantined_links()?;
+    let mut removed_count = 0;
+    for link in links
+        .iter()
+        .filter(|link| link_id.is_none() || link_id == Some(link.id))
+    {
+        log::info!("removing quarantined link {}: {}", link.id, link.link);
+        removed_count += 1;
+        if !dry_run {
+            if let Some(image) = db.mirrored_image(link.id)? {
+                mirror::forget(&db, &image).await?;
+            }
+            db.delete(link.id)
+                .map_err(|e| PurgeError::DatabasDelete(link.id, e))?;
+        }
+    }
+    log::info!("Removed {} quarantined links", removed_count);
+    Ok(())
+}
+
+/// Puts the quarantined links back in the searches, or only `link_id`
+pub async fn restore_quarantine(
+    db_pool: db::DbPool,
+    link_id: Option<i32>,
+) -> Result<(), PurgeError> {
+    let restored_count = db_pool.get().await?.restore_links(link_id)?;
+    log::info!("Restored {} quarantined links", restored_count);
+    Ok(())
+}
+
 const MAX_ATTEMPTS: u32 = 5;
 
 async fn check_link(client: &Client, link: &model::Link) -> Result<bool, PurgeError> {
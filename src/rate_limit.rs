@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+#[derive(Debug)]
+struct Buckets<K> {
+    buckets: HashMap<K, Bucket>,
+    swept_at: Instant,
+}
+
+/// Token bucket rate limiter, keeping a bucket for each key.
+/// A bucket holds at most `capacity` tokens and gets `capacity` new ones every `period`.
+/// The buckets left untouched for a `period` are full again, they are dropped once per `period`
+#[derive(Debug)]
+pub struct RateLimiter<K> {
+    capacity: f64,
+    period: Duration,
+    buckets: Mutex<Buckets<K>>,
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    pub fn new(capacity: u32, period: Duration) -> Self {
+        RateLimiter {
+            capacity: f64::from(capacity),
+            period,
+            buckets: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                swept_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes a token from the key's bucket, returns false if it is empty
+    pub fn check(&self, key: K) -> bool {
+        self.check_at(key, Instant::now())
+    }
+
+    pub fn check_at(&self, key: K, now: Instant) -> bool {
+        self.take(key, self.capacity, now)
+    }
+
+    /// Like `check_at`, for a key whose bucket holds `capacity` tokens instead of the limiter's
+    pub fn check_capacity_at(&self, key: K, capacity: u32, now: Instant) -> bool {
+        self.take(key, f64::from(capacity), now)
+    }
+
+    /// Gives back a token taken from the key's bucket, when the action it allowed was denied
+    /// by another limit
+    pub fn refund(&self, key: &K) {
+        let mut buckets = self.buckets.lock().unwrap();
+        if let Some(bucket) = buckets.buckets.get_mut(key) {
+            bucket.tokens = (bucket.tokens + 1.).min(self.capacity);
+        }
+    }
+
+    fn take(&self, key: K, capacity: f64, now: Instant) -> bool {
+        let mut buckets = self.buckets.lock().unwrap();
+        if now.saturating_duration_since(buckets.swept_at) >= self.period {
+            let period = self.period;
+            buckets
+                .buckets
+                .retain(|_, bucket| now.saturating_duration_since(bucket.updated_at) < period);
+            buckets.swept_at = now;
+        }
+        let bucket = buckets.buckets.entry(key).or_insert(Bucket {
+            tokens: capacity,
+            updated_at: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated_at);
+        bucket.tokens = (bucket.tokens
+            + capacity * elapsed.as_secs_f64() / self.period.as_secs_f64())
+        .min(capacity);
+        bucket.updated_at = now;
+        if bucket.tokens < 1. {
+            return false;
+        }
+        bucket.tokens -= 1.;
+        true
+    }
+}
+
+#[test]
+fn test_rate_limiter_refills() {
+    let limiter = RateLimiter::new(2, Duration::from_secs(60));
+    let start = Instant::now();
+    assert!(limiter.check_at(1, start));
+    assert!(limiter.check_at(1, start));
+    assert!(!limiter.check_at(1, start));
+    assert!(limiter.check_at(2, start));
+    assert!(!limiter.check_at(1, start + Duration::from_secs(10)));
+    assert!(limiter.check_at(1, start + Duration::from_secs(31)));
+}
+
+#[test]
+fn test_rate_limiter_drops_full_buckets() {
+    let limiter = RateLimiter::new(2, Duration::from_secs(60));
+    let start = Instant::now();
+    assert!(limiter.check_at(1, start));
+    assert!(limiter.check_at(2, start + Duration::from_secs(30)));
+    assert!(limiter.check_at(3, start + Duration::from_secs(70)));
+    let buckets = limiter.buckets.lock().unwrap();
+    let mut keys: Vec<_> = buckets.buckets.keys().copied().collect();
+    keys.sort_unstable();
+    assert_eq!(vec![2, 3], keys);
+}
@@ -17,15 +17,43 @@ pub struct Listing {
 
 #[derive(Deserialize, Debug)]
 pub struct Link {
-    // pub subreddit: String,
+    pub subreddit: String,
     pub title: String,
     // pub name: String,
     // pub over_18: bool,
     // pub pinned: bool,
     pub url: String,
+    pub permalink: String,
     // pub spoiler: bool,
     // pub selftext: String,
-    // pub score: i64,
+    pub score: i64,
+    pub created_utc: f64,
+    pub preview: Option<Preview>,
+}
+
+impl Link {
+    /// Width and height of the image, as given in the preview of the post
+    pub fn image_size(&self) -> Option<(i32, i32)> {
+        let source = &self.preview.as_ref()?.images.first()?.source;
+        Some((source.width, source.height))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Preview {
+    pub images: Vec<PreviewImage>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PreviewImage {
+    pub source: PreviewSource,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PreviewSource {
+    // pub url: String,
+    pub width: i32,
+    pub height: i32,
 }
 
 #[derive(Debug)]
@@ -1,16 +1,60 @@
 use crate::db;
+use crate::dedupe;
+use crate::mirror::Mirror;
 use crate::reddit_api;
+use crate::utils::unix_now;
 use crate::YuribotError;
 
+use std::convert::TryInto;
 use std::time::Duration;
 
 use futures::StreamExt;
 use tokio::time;
 use tokio_stream::wrappers::IntervalStream;
 
+/// Media type stored for the search filters, see `search::MEDIA_TYPES`
+fn media_type(url: &str) -> Option<&'static str> {
+    let url = url.to_lowercase();
+    if url.ends_with(".png") {
+        Some("png")
+    } else if url.ends_with(".jpg") || url.ends_with(".jpeg") {
+        Some("jpg")
+    } else if url.ends_with(".gif") {
+        Some("gif")
+    } else {
+        None
+    }
+}
+
+/// GIFs are left out, they would be sent as a photo of their first frame
 fn is_image_url(url: &str) -> bool {
-    url.ends_with(".png") || url.ends_with(".jpg") || url.ends_with(".jpeg")
+    matches!(media_type(url), Some("png") | Some("jpg"))
+}
+
+/// Text between the first `open` and the following `close`, and the position after `close`
+fn enclosed(text: &str, open: char, close: char) -> Option<(&str, usize)> {
+    let start = text.find(open)? + open.len_utf8();
+    let length = text[start..].find(close)?;
+    Some((
+        text[start..start + length].trim(),
+        start + length + close.len_utf8(),
+    ))
+}
+
+/// Artist and series of a title following the `Title [Artist] (Series)` convention of the
+/// subreddits, the series being looked for after the artist
+fn title_tags(title: &str) -> (Option<&str>, Option<&str>) {
+    let (artist, rest) = match enclosed(title, '[', ']') {
+        Some((artist, end)) => (Some(artist), &title[end..]),
+        None => (None, title),
+    };
+    let series = enclosed(rest, '(', ')').map(|(series, _)| series);
+    (
+        artist.filter(|artist| !artist.is_empty()),
+        series.filter(|series| !series.is_empty()),
+    )
 }
+
 async fn pull_links(
     database: &db::Database,
     reddit: &reddit_api::Reddit,
@@ -25,21 +69,61 @@ async fn pull_links(
             link_number,
         )
         .await?;
-    let insert_count = database.insert_links(
-        &links
-            .iter()
-            .filter(|link| is_image_url(&link.url))
-            .map(|link| db::model::NewLink {
+    let subreddits = links
+        .iter()
+        .map(|link| link.subreddit.to_lowercase())
+        .collect::<Vec<String>>();
+    let new_links = links
+        .iter()
+        .zip(&subreddits)
+        .filter(|(link, _)| is_image_url(&link.url))
+        .map(|(link, subreddit)| {
+            let (artist, series) = title_tags(&link.title);
+            let (width, height) = link.image_size().unzip();
+            db::model::NewLink {
                 link: &link.url,
                 title: &link.title,
-            })
-            .collect::<Vec<db::model::NewLink>>(),
-    )?;
+                score: link.score.try_into().unwrap_or(i32::MAX),
+                created_utc: link.created_utc as i64,
+                permalink: Some(&link.permalink),
+                subreddit: Some(subreddit),
+                media_type: media_type(&link.url),
+                artist,
+                series,
+                width,
+                height,
+            }
+        })
+        .collect::<Vec<db::model::NewLink>>();
+    let insert_count = database.insert_links(&new_links)?;
+    database.refresh_links(&new_links)?;
     info!("inserted {} new links in database", insert_count);
     Ok(())
 }
 
-pub async fn run_scrapper(db_pool: db::DbPool, rd_pool: reddit_api::RdPool) {
+/// Queues the links inserted after `after_id` for each channel
+fn queue_channel_posts(
+    database: &db::Database,
+    channel_ids: &[i64],
+    after_id: i32,
+) -> Result<(), YuribotError> {
+    let now = unix_now();
+    for &channel_id in channel_ids {
+        let queued = database.queue_channel_posts(channel_id, after_id, now)?;
+        if queued > 0 {
+            info!("queued {} links for channel {}", queued, channel_id);
+        }
+    }
+    Ok(())
+}
+
+pub async fn run_scrapper(
+    db_pool: db::DbPool,
+    rd_pool: reddit_api::RdPool,
+    channel_ids: Vec<i64>,
+    mirror: Option<Mirror>,
+    duplicate_max_distance: u32,
+) {
     let database = db_pool.get().await.expect("can't get database connection");
     let reddit = rd_pool
         .get()
@@ -47,9 +131,28 @@ pub async fn run_scrapper(db_pool: db::DbPool, rd_pool: reddit_api::RdPool) {
         .expect("can't get reddit api connection");
     let mut interval = IntervalStream::new(time::interval(Duration::from_secs(30 * 60)));
     while let Some(_) = interval.next().await {
+        let last_id = match database.last_link_id() {
+            Ok(last_id) => last_id,
+            Err(e) => {
+                error!("{}", e);
+                continue;
+            }
+        };
         if let Err(e) = pull_links(&database, &reddit, 3, reddit_api::MaxTime::DAY).await {
             error!("{}", e);
         }
+        if let Some(mirror) = &mirror {
+            if let Err(e) = mirror.mirror_links(&database, last_id).await {
+                error!("failed to mirror the new links: {}", e);
+            }
+        }
+        // Before queueing the channel posts, which leaves out the reposts
+        if let Err(e) = dedupe::dedupe_new_links(&database, last_id, duplicate_max_distance).await {
+            error!("failed to look for reposts of the new links: {}", e);
+        }
+        if let Err(e) = queue_channel_posts(&database, &channel_ids, last_id) {
+            error!("failed to queue the channel posts: {}", e);
+        }
     }
 }
 
@@ -57,9 +160,35 @@ pub async fn seed_database(
     nb_posts: usize,
     rd_pool: reddit_api::RdPool,
     db_pool: db::DbPool,
+    mirror: Option<Mirror>,
+    duplicate_max_distance: u32,
 ) -> Result<(), YuribotError> {
     let reddit = rd_pool.get().await?;
     let database = db_pool.get().await?;
+    let last_id = database.last_link_id()?;
     pull_links(&database, &reddit, nb_posts, reddit_api::MaxTime::ALL).await?;
+    if let Some(mirror) = &mirror {
+        mirror.mirror_links(&database, last_id).await?;
+    }
+    dedupe::dedupe_new_links(&database, last_id, duplicate_max_distance).await?;
     Ok(())
 }
+
+#[test]
+fn test_title_tags() {
+    assert_eq!(
+        (Some("Kase Daiki"), Some("Bloom Into You")),
+        title_tags("Touko and Yuu [Kase Daiki] (Bloom Into You)")
+    );
+    assert_eq!((Some("artist"), None), title_tags("Cute (OC) [artist]"));
+    assert_eq!((None, Some("OC")), title_tags("Date night [] (OC)"));
+    assert_eq!((None, None), title_tags("Date night [unclosed (no series"));
+}
+
+#[test]
+fn test_is_image_url() {
+    assert!(is_image_url("https://i.redd.it/abc.PNG"));
+    assert!(!is_image_url("https://i.redd.it/abc.gif"));
+    assert!(!is_image_url("https://v.redd.it/abc"));
+    assert_eq!(Some("jpg"), media_type("https://i.imgur.com/abc.JPEG"));
+}
@@ -0,0 +1,732 @@
+use std::collections::HashSet;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SearchError {
+    #[error("A quote is never closed in your search")]
+    UnclosedQuote,
+    #[error("'-' and '*' must be attached to a word, like -sad or neko*")]
+    EmptyTerm,
+    #[error("OR must be placed between two words, like maid OR nurse")]
+    MisplacedOr,
+    #[error("A search can't only exclude words, add at least one word to look for")]
+    OnlyExclusions,
+    #[error("Filters like sub: or type: can't be excluded with '-'")]
+    ExcludedFilter,
+    #[error("'{0}:' needs a value, like sub:wholesomeyuri or type:png")]
+    MissingFilterValue(String),
+    #[error("'{0}' isn't a date, write it like 2023, 2023-06 or 2023-06-15")]
+    InvalidDate(String),
+    #[error("'{0}' isn't a score, write it like score:>500 or score:<10")]
+    InvalidScore(String),
+    #[error("'{0}' isn't a media type, use jpg, png or gif")]
+    UnknownMediaType(String),
+    #[error("'{0}' isn't an orientation, use portrait or landscape")]
+    UnknownOrientation(String),
+    #[error("'{0}' isn't a resolution, write it in pixels like res:1000")]
+    InvalidResolution(String),
+}
+
+/// Media types stored for the links, see `scrapper::media_type`
+pub const MEDIA_TYPES: [&str; 3] = ["jpg", "png", "gif"];
+
+const FILTERS: [&str; 9] = [
+    "sub",
+    "before",
+    "after",
+    "type",
+    "score",
+    "artist",
+    "series",
+    "orientation",
+    "res",
+];
+
+/// Shape of an image, a square image is neither
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+}
+
+/// A parsed search: the words compiled to a full text MATCH expression that is safe to pass to
+/// sqlite, and the filters on the links' metadata
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Search {
+    fts: Option<String>,
+    cjk: bool,
+    pub subreddit: Option<String>,
+    /// Only links posted before this timestamp
+    pub before: Option<i64>,
+    /// Only links posted at or after this timestamp
+    pub after: Option<i64>,
+    pub media_type: Option<String>,
+    pub min_score: Option<i32>,
+    pub max_score: Option<i32>,
+    /// Part of the artist's name, as written in the titles' brackets
+    pub artist: Option<String>,
+    /// Part of the series' name, as written in the titles' parentheses
+    pub series: Option<String>,
+    /// Only images of known size with this orientation
+    pub orientation: Option<Orientation>,
+    /// Smallest width and height of the images, those of unknown size are left out
+    pub min_resolution: Option<i32>,
+}
+
+impl Search {
+    /// Full text MATCH expression, `None` if the search matches everything
+    pub fn fts(&self) -> Option<&str> {
+        self.fts.as_deref()
+    }
+
+    /// Whether the MATCH expression is for the CJK index, see `cjk_grams`
+    pub fn is_cjk(&self) -> bool {
+        self.cjk
+    }
+
+    /// The search with the larger of its minimum resolution and `min_resolution`, to apply
+    /// the minimum resolution of a chat
+    pub fn with_min_resolution(&self, min_resolution: Option<i32>) -> Search {
+        Search {
+            min_resolution: self.min_resolution.max(min_resolution),
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Term {
+        text: String,
+        prefix: bool,
+        excluded: bool,
+    },
+    Filter {
+        key: String,
+        value: String,
+    },
+    Or,
+}
+
+fn escape_fts(search: &str) -> String {
+    let mut escaped = String::with_capacity(search.len() + 2);
+    escaped.push('"');
+    for c in search.chars() {
+        if c == '"' {
+            escaped.push_str("\"\"");
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn is_cjk_char(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}' // Hiragana and Katakana
+        | '\u{3400}'..='\u{4dbf}' // CJK Extension A
+        | '\u{4e00}'..='\u{9fff}' // CJK Unified Ideographs
+        | '\u{ac00}'..='\u{d7af}' // Hangul Syllables
+        | '\u{f900}'..='\u{faff}' // CJK Compatibility Ideographs
+        | '\u{ff66}'..='\u{ff9f}' // Halfwidth Katakana
+        | '\u{20000}'..='\u{2fa1f}' // CJK Extensions B to F and Compatibility Supplement
+    )
+}
+
+/// Splits the runs of CJK characters in overlapping bigrams, the unicode61 tokenizer of the
+/// full text index would otherwise take a whole sentence as a single word.
+/// Returns the split text and whether it ends with a lone CJK character
+fn split_cjk(text: &str, with_last: bool) -> (String, bool) {
+    let mut words = Vec::new();
+    let mut run = Vec::new();
+    let mut other = String::new();
+    let mut lone = false;
+    for c in text.chars().chain(std::iter::once(' ')) {
+        if is_cjk_char(c) {
+            if !other.is_empty() {
+                words.push(std::mem::take(&mut other));
+            }
+            run.push(c);
+            continue;
+        }
+        lone = run.len() == 1;
+        if run.len() == 1 {
+            words.push(run[0].to_string());
+        } else if !run.is_empty() {
+            words.extend(run.windows(2).map(|pair| pair.iter().collect::<String>()));
+            if with_last {
+                words.push(run[run.len() - 1].to_string());
+            }
+        }
+        run.clear();
+        if !c.is_whitespace() {
+            lone = false;
+            other.push(c);
+        } else if !other.is_empty() {
+            words.push(std::mem::take(&mut other));
+        }
+    }
+    (words.join(" "), lone)
+}
+
+/// Splits the text where it goes from CJK characters to other ones, the runs of CJK characters
+/// are indexed apart from the text around them
+fn script_runs(text: &str) -> Vec<&str> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    let mut run_is_cjk = None;
+    for (i, c) in text.char_indices().filter(|(_, c)| !c.is_whitespace()) {
+        let is_cjk = is_cjk_char(c);
+        if run_is_cjk == Some(!is_cjk) {
+            runs.push(&text[start..i]);
+            start = i;
+        }
+        run_is_cjk = Some(is_cjk);
+    }
+    runs.push(&text[start..]);
+    runs
+}
+
+/// Title as stored in the CJK full text index. Each CJK character is followed by the next one,
+/// or is the last of its run, so a search for any part of the title can be found
+pub fn cjk_grams(title: &str) -> String {
+    split_cjk(title, true).0
+}
+
+fn read_quoted(chars: &mut Peekable<Chars>) -> Result<String, SearchError> {
+    let mut text = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(text),
+            Some(c) => text.push(c),
+            None => return Err(SearchError::UnclosedQuote),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, SearchError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    loop {
+        while matches!(chars.peek(), Some(c) if c.is_whitespace()) {
+            chars.next();
+        }
+        let excluded = match chars.peek() {
+            None => return Ok(tokens),
+            Some('-') => {
+                chars.next();
+                true
+            }
+            Some(_) => false,
+        };
+        let mut text = String::new();
+        let quoted = chars.peek() == Some(&'"');
+        if quoted {
+            chars.next();
+            text = read_quoted(&mut chars)?;
+        } else {
+            while let Some(c) = chars.peek() {
+                if c.is_whitespace() || *c == '"' {
+                    break;
+                }
+                text.push(*c);
+                chars.next();
+            }
+        }
+        let mut prefix = false;
+        if quoted {
+            if chars.peek() == Some(&'*') {
+                chars.next();
+                prefix = true;
+            }
+        } else {
+            if text == "OR" && !excluded {
+                tokens.push(Token::Or);
+                continue;
+            }
+            if let Some(i) = text.find(':') {
+                if FILTERS.contains(&&text[..i]) {
+                    if excluded {
+                        return Err(SearchError::ExcludedFilter);
+                    }
+                    let mut value = text[i + 1..].to_owned();
+                    if value.is_empty() && chars.peek() == Some(&'"') {
+                        chars.next();
+                        value = read_quoted(&mut chars)?;
+                    }
+                    tokens.push(Token::Filter {
+                        key: text[..i].to_owned(),
+                        value,
+                    });
+                    continue;
+                }
+            }
+            while text.ends_with('*') {
+                text.pop();
+                prefix = true;
+            }
+        }
+        if text.trim().is_empty() {
+            return Err(SearchError::EmptyTerm);
+        }
+        tokens.push(Token::Term {
+            text,
+            prefix,
+            excluded,
+        });
+    }
+}
+
+/// Days since the unix epoch of a date of the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    days_from_civil(next_year, next_month, 1) - days_from_civil(year, month, 1)
+}
+
+/// Start and end timestamps of a year, month or day written like 2023, 2023-06 or 2023-06-15
+fn parse_period(value: &str) -> Option<(i64, i64)> {
+    let parts = value
+        .split('-')
+        .map(|part| {
+            if part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            part.parse::<i64>().ok()
+        })
+        .collect::<Option<Vec<i64>>>()?;
+    if value.split('-').next()?.len() != 4 {
+        return None;
+    }
+    let (start, end) = match parts[..] {
+        [year] => (days_from_civil(year, 1, 1), days_from_civil(year + 1, 1, 1)),
+        [year, month] if (1..=12).contains(&month) => {
+            let start = days_from_civil(year, month, 1);
+            (start, start + days_in_month(year, month))
+        }
+        [year, month, day]
+            if (1..=12).contains(&month) && (1..=days_in_month(year, month)).contains(&day) =>
+        {
+            let start = days_from_civil(year, month, day);
+            (start, start + 1)
+        }
+        _ => return None,
+    };
+    Some((start * 24 * 60 * 60, end * 24 * 60 * 60))
+}
+
+fn apply_filter(search: &mut Search, key: &str, value: &str) -> Result<(), SearchError> {
+    if value.trim().is_empty() {
+        return Err(SearchError::MissingFilterValue(key.to_owned()));
+    }
+    let invalid_date = || SearchError::InvalidDate(value.to_owned());
+    match key {
+        "artist" => search.artist = Some(value.trim().to_owned()),
+        "series" => search.series = Some(value.trim().to_owned()),
+        "sub" => {
+            let subreddit = value.trim_start_matches("/r/").trim_start_matches("r/");
+            search.subreddit = Some(subreddit.to_lowercase());
+        }
+        "before" => search.before = Some(parse_period(value).ok_or_else(invalid_date)?.0),
+        "after" => search.after = Some(parse_period(value).ok_or_else(invalid_date)?.1),
+        "type" => {
+            let media_type = match value.to_lowercase().as_str() {
+                "jpeg" => "jpg".to_owned(),
+                media_type if MEDIA_TYPES.contains(&media_type) => media_type.to_owned(),
+                _ => return Err(SearchError::UnknownMediaType(value.to_owned())),
+            };
+            search.media_type = Some(media_type);
+        }
+        "score" => {
+            let invalid_score = || SearchError::InvalidScore(value.to_owned());
+            let (comparison, number) = match value.find(|c: char| c.is_ascii_digit()) {
+                Some(i) => value.split_at(i),
+                None => return Err(invalid_score()),
+            };
+            let number: i32 = number.parse().map_err(|_| invalid_score())?;
+            match comparison {
+                "" | ">=" => search.min_score = Some(number),
+                ">" => search.min_score = Some(number.saturating_add(1)),
+                "<=" => search.max_score = Some(number),
+                "<" => search.max_score = Some(number.saturating_sub(1)),
+                _ => return Err(invalid_score()),
+            }
+        }
+        "orientation" => {
+            let orientation = match value.to_lowercase().as_str() {
+                "portrait" => Orientation::Portrait,
+                "landscape" => Orientation::Landscape,
+                _ => return Err(SearchError::UnknownOrientation(value.to_owned())),
+            };
+            search.orientation = Some(orientation);
+        }
+        "res" => {
+            let invalid_resolution = || SearchError::InvalidResolution(value.to_owned());
+            if !value.chars().all(|c| c.is_ascii_digit()) {
+                return Err(invalid_resolution());
+            }
+            search.min_resolution = Some(value.parse().map_err(|_| invalid_resolution())?);
+        }
+        _ => unreachable!("unknown filter {}", key),
+    }
+    Ok(())
+}
+
+/// Parses a user search: words are all required, `OR` between words accepts either side,
+/// `-word` excludes a word, `"some words"` looks for a phrase and `word*` for a prefix.
+/// `sub:`, `before:`, `after:`, `type:`, `score:`, `orientation:` and `res:` filter on the links'
+/// metadata
+pub fn parse(input: &str) -> Result<Search, SearchError> {
+    let mut search = Search::default();
+    let mut tokens = Vec::new();
+    for token in tokenize(input)? {
+        match token {
+            Token::Filter { key, value } => apply_filter(&mut search, &key, &value)?,
+            token => tokens.push(token),
+        }
+    }
+    if tokens.is_empty() {
+        return Ok(search);
+    }
+    search.cjk = input.contains(is_cjk_char);
+    let mut groups = Vec::new();
+    for group in tokens.split(|token| *token == Token::Or) {
+        if group.is_empty() {
+            return Err(SearchError::MisplacedOr);
+        }
+        let mut included = Vec::new();
+        let mut excluded = Vec::new();
+        for token in group {
+            if let Token::Term {
+                text,
+                prefix,
+                excluded: is_excluded,
+            } = token
+            {
+                let mut term = escape_fts(text);
+                if search.cjk {
+                    // The last character of a run is indexed after its bigrams, so the runs
+                    // can't be in the same phrase as the text after them
+                    let runs = script_runs(text);
+                    let mut phrases: Vec<String> = runs
+                        .iter()
+                        .enumerate()
+                        .map(|(i, run)| {
+                            let (grams, lone) = split_cjk(run, false);
+                            let mut phrase = escape_fts(&grams);
+                            // A lone character has to be looked for at the start of the bigrams
+                            if lone || (*prefix && i == runs.len() - 1) {
+                                phrase.push('*');
+                            }
+                            phrase
+                        })
+                        .collect();
+                    term = if phrases.len() == 1 {
+                        phrases.remove(0)
+                    } else {
+                        format!("({})", phrases.join(" AND "))
+                    };
+                } else if *prefix {
+                    term.push('*');
+                }
+                if *is_excluded {
+                    excluded.push(term);
+                } else {
+                    included.push(term);
+                }
+            }
+        }
+        if included.is_empty() {
+            return Err(SearchError::OnlyExclusions);
+        }
+        let mut expression = format!("({})", included.join(" AND "));
+        for term in excluded {
+            expression.push_str(" NOT ");
+            expression.push_str(&term);
+        }
+        groups.push(format!("({})", expression));
+    }
+    search.fts = Some(groups.join(" OR "));
+    Ok(search)
+}
+
+/// Number of insertions, deletions, substitutions and swaps of adjacent characters to turn a into b
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut distance = (distances[i - 1][j - 1] + cost)
+                .min(distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+    distances[a.len()][b.len()]
+}
+
+/// Closest word of the vocabulary, close enough to be a typo. Ties go to the first word
+fn closest_word<'a>(word: &str, vocabulary: &'a [String]) -> Option<&'a str> {
+    let length = word.chars().count();
+    let max_distance = if length <= 4 { 1 } else { 2 };
+    let mut closest = None;
+    let mut closest_distance = max_distance + 1;
+    for known in vocabulary {
+        let known_length = known.chars().count();
+        if known_length.max(length) - known_length.min(length) >= closest_distance {
+            continue;
+        }
+        let distance = edit_distance(word, known);
+        if distance < closest_distance {
+            closest = Some(known.as_str());
+            closest_distance = distance;
+        }
+    }
+    closest
+}
+
+/// Rewrites the search with the closest word of the vocabulary, the words of the full text index,
+/// in place of each unknown word. `None` if there is no word to correct
+pub fn suggest(input: &str, vocabulary: &[String]) -> Option<String> {
+    let known: HashSet<&str> = vocabulary.iter().map(|word| word.as_str()).collect();
+    let mut corrected = false;
+    let mut words = Vec::new();
+    for token in tokenize(input).ok()? {
+        let word = match token {
+            Token::Term {
+                text,
+                prefix,
+                excluded,
+            } => {
+                let mut text = text.to_lowercase();
+                let is_word = text.chars().count() >= 3
+                    && text.chars().all(char::is_alphanumeric)
+                    && !text.contains(is_cjk_char);
+                if is_word && !prefix && !excluded && !known.contains(text.as_str()) {
+                    if let Some(closest) = closest_word(&text, vocabulary) {
+                        text = closest.to_owned();
+                        corrected = true;
+                    }
+                }
+                if text.contains(|c: char| c.is_whitespace() || c == ':') || text == "or" {
+                    text = format!("\"{}\"", text);
+                }
+                format!(
+                    "{}{}{}",
+                    if excluded { "-" } else { "" },
+                    text,
+                    if prefix { "*" } else { "" }
+                )
+            }
+            Token::Filter { key, value } if value.contains(char::is_whitespace) => {
+                format!("{}:\"{}\"", key, value)
+            }
+            Token::Filter { key, value } => format!("{}:{}", key, value),
+            Token::Or => "OR".to_owned(),
+        };
+        words.push(word);
+    }
+    if corrected {
+        Some(words.join(" "))
+    } else {
+        None
+    }
+}
+
+#[test]
+fn test_escape_fts() {
+    assert_eq!("\"test string 132\"", escape_fts("test string 132"));
+    assert_eq!(
+        "\"test \"\"string\"\" 132\"",
+        escape_fts("test \"string\" 132")
+    );
+}
+
+#[test]
+fn test_parse_search() {
+    let fts = |input| parse(input).map(|s| s.fts);
+    assert_eq!(Ok(None), fts("  "));
+    assert_eq!(
+        Ok(Some("((\"maid\" AND \"outfit\"))".to_owned())),
+        fts("maid outfit")
+    );
+    assert_eq!(
+        Ok(Some(
+            "((\"maid\")) OR ((\"nurse\" AND \"cat\"*) NOT \"sad\")".to_owned()
+        )),
+        fts("maid OR nurse cat* -sad")
+    );
+    assert_eq!(
+        Ok(Some("((\"maid outfit\"*)) OR ((\"or\"))".to_owned())),
+        fts("\"maid outfit\"* OR or")
+    );
+    assert_eq!(Ok(Some("((\"a\" AND \"b\"))".to_owned())), fts("a\"b\""));
+}
+
+#[test]
+fn test_parse_search_filters() {
+    let search =
+        parse("maid sub:r/WholesomeYuri after:2024-06 before:2025 type:JPEG score:>500").unwrap();
+    assert_eq!(Some("((\"maid\"))"), search.fts());
+    assert_eq!(Some("wholesomeyuri".to_owned()), search.subreddit);
+    assert_eq!(Some(1_719_792_000), search.after);
+    assert_eq!(Some(1_735_689_600), search.before);
+    assert_eq!(Some("jpg".to_owned()), search.media_type);
+    assert_eq!(Some(501), search.min_score);
+    assert_eq!(None, search.max_score);
+
+    let search = parse("score:<=10 before:2024-02-29 nekomimi:yes").unwrap();
+    assert_eq!(Some("((\"nekomimi:yes\"))"), search.fts());
+    assert_eq!(Some(10), search.max_score);
+    assert_eq!(Some(1_709_164_800), search.before);
+    assert_eq!(None, parse("type:gif").unwrap().fts());
+
+    let search = parse("artist:\"Kase Daiki\" series:bloom kiss").unwrap();
+    assert_eq!(Some("((\"kiss\"))"), search.fts());
+    assert_eq!(Some("Kase Daiki".to_owned()), search.artist);
+    assert_eq!(Some("bloom".to_owned()), search.series);
+
+    let search = parse("orientation:Portrait res:1000").unwrap();
+    assert_eq!(Some(Orientation::Portrait), search.orientation);
+    assert_eq!(Some(1000), search.min_resolution);
+    assert_eq!(
+        Some(1000),
+        search.with_min_resolution(Some(500)).min_resolution
+    );
+    assert_eq!(
+        Some(500),
+        Search::default()
+            .with_min_resolution(Some(500))
+            .min_resolution
+    );
+}
+
+#[test]
+fn test_parse_search_errors() {
+    assert_eq!(Err(SearchError::UnclosedQuote), parse("\"maid outfit"));
+    assert_eq!(Err(SearchError::EmptyTerm), parse("maid - outfit"));
+    assert_eq!(Err(SearchError::EmptyTerm), parse("*"));
+    assert_eq!(Err(SearchError::MisplacedOr), parse("OR maid"));
+    assert_eq!(Err(SearchError::MisplacedOr), parse("maid OR OR nurse"));
+    assert_eq!(Err(SearchError::OnlyExclusions), parse("-sad"));
+    assert_eq!(Err(SearchError::OnlyExclusions), parse("maid OR -sad"));
+    assert_eq!(Err(SearchError::ExcludedFilter), parse("maid -sub:yuri"));
+    assert_eq!(
+        Err(SearchError::MissingFilterValue("sub".to_owned())),
+        parse("sub:")
+    );
+    assert_eq!(
+        Err(SearchError::MissingFilterValue("artist".to_owned())),
+        parse("artist:\" \"")
+    );
+    assert_eq!(Err(SearchError::UnclosedQuote), parse("artist:\"kase"));
+    for date in &["2023-02-29", "2023-13", "23", "2023-", "-2023", "+2023"] {
+        assert_eq!(
+            Err(SearchError::InvalidDate(date.to_string())),
+            parse(&format!("before:{}", date))
+        );
+    }
+    assert_eq!(
+        Err(SearchError::InvalidScore("=>5".to_owned())),
+        parse("score:=>5")
+    );
+    assert_eq!(
+        Err(SearchError::UnknownMediaType("webm".to_owned())),
+        parse("type:webm")
+    );
+    assert_eq!(
+        Err(SearchError::UnknownOrientation("square".to_owned())),
+        parse("orientation:square")
+    );
+    assert_eq!(
+        Err(SearchError::InvalidResolution("-5".to_owned())),
+        parse("res:-5")
+    );
+}
+
+#[test]
+fn test_edit_distance() {
+    assert_eq!(0, edit_distance("maid", "maid"));
+    assert_eq!(1, edit_distance("maid", "mad"));
+    assert_eq!(1, edit_distance("maid", "miad"));
+    assert_eq!(2, edit_distance("nekomimi", "nekomini2"));
+    assert_eq!(3, edit_distance("", "百合子"));
+}
+
+#[test]
+fn test_suggest() {
+    let vocabulary: Vec<String> = vec!["maid", "outfit", "made", "nurse", "百合"]
+        .into_iter()
+        .map(String::from)
+        .collect();
+    assert_eq!(
+        Some("maid outfit".to_owned()),
+        suggest("Maid outfti", &vocabulary)
+    );
+    assert_eq!(
+        Some("maid OR nurse -sad type:png".to_owned()),
+        suggest("miad OR nurse -sad type:png", &vocabulary)
+    );
+    assert_eq!(
+        Some("\"maid outfit\" nurse".to_owned()),
+        suggest("\"maid outfit\" nrse", &vocabulary)
+    );
+    assert_eq!(None, suggest("maid outf* -nrse 百合", &vocabulary));
+    assert_eq!(None, suggest("zzzzzz", &vocabulary));
+    assert_eq!(None, suggest("\"unclosed", &vocabulary));
+}
+
+#[test]
+fn test_cjk_grams() {
+    assert_eq!(
+        "百合 合 maid 姉妹 妹 [artist]",
+        cjk_grams("百合 maid姉妹 [artist]")
+    );
+    assert_eq!(
+        "wholesome ゆり りの のせ せか かい い 2",
+        cjk_grams("wholesome ゆりのせかい 2")
+    );
+    assert_eq!("a 百 b", cjk_grams("a百b"));
+}
+
+#[test]
+fn test_parse_cjk_search() {
+    let search = parse("百合姉妹 maid").unwrap();
+    assert!(search.is_cjk());
+    assert_eq!(Some("((\"百合 合姉 姉妹\" AND \"maid\"))"), search.fts());
+    let search = parse("百 -maid").unwrap();
+    assert_eq!(Some("((\"百\"*) NOT \"maid\")"), search.fts());
+    let search = parse("百合maid* -\"姉妹 maid\"").unwrap();
+    assert_eq!(
+        Some("(((\"百合\" AND \"maid\"*)) NOT (\"姉妹\" AND \"maid\"))"),
+        search.fts()
+    );
+    assert!(!parse("maid").unwrap().is_cjk());
+}
@@ -0,0 +1,217 @@
+use crate::db::{self, model};
+use crate::search::Search;
+use crate::utils::unix_now;
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use rand::Rng;
+
+const SECONDS_PER_DAY: f64 = 24. * 60. * 60.;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SelectionMode {
+    Uniform,
+    Weighted,
+    Fresh,
+}
+
+impl SelectionMode {
+    pub const ALL: [SelectionMode; 3] = [
+        SelectionMode::Uniform,
+        SelectionMode::Weighted,
+        SelectionMode::Fresh,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SelectionMode::Uniform => "uniform",
+            SelectionMode::Weighted => "weighted",
+            SelectionMode::Fresh => "fresh",
+        }
+    }
+}
+
+impl FromStr for SelectionMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        SelectionMode::ALL
+            .iter()
+            .find(|mode| mode.as_str() == s)
+            .copied()
+            .ok_or(())
+    }
+}
+
+/// Tuning of the non uniform selection modes, read from the config file
+#[derive(Debug, Clone, Copy)]
+pub struct Weights {
+    /// links are weighted by `(score + 1) ^ score_exponent` in weighted mode
+    pub score_exponent: f64,
+    /// number of reddit points an in-chat vote is worth in weighted mode
+    pub vote_weight: f64,
+    /// in fresh mode, a link is half as likely to be picked every `fresh_half_life_days`
+    pub fresh_half_life_days: f64,
+}
+
+impl Weights {
+    fn weight(
+        &self,
+        mode: SelectionMode,
+        candidate: &model::Candidate,
+        votes: i64,
+        now: i64,
+    ) -> f64 {
+        match mode {
+            SelectionMode::Uniform => 1.,
+            SelectionMode::Weighted => {
+                let score = f64::from(candidate.score) + votes as f64 * self.vote_weight;
+                (score.max(0.) + 1.).powf(self.score_exponent)
+            }
+            // Links scraped before the post dates were stored have no date, they are only
+            // picked when no other link matches
+            SelectionMode::Fresh if candidate.created_utc == 0 => 0.,
+            SelectionMode::Fresh => {
+                let age_days = (now - candidate.created_utc).max(0) as f64 / SECONDS_PER_DAY;
+                (-age_days / self.fresh_half_life_days).exp2()
+            }
+        }
+    }
+
+    /// Index of a candidate picked at random according to their weights
+    fn pick<R: Rng>(
+        &self,
+        rng: &mut R,
+        mode: SelectionMode,
+        candidates: &[model::Candidate],
+        votes: &HashMap<i32, i64>,
+        now: i64,
+    ) -> Option<usize> {
+        if candidates.is_empty() {
+            return None;
+        }
+        let weights: Vec<f64> = candidates
+            .iter()
+            .map(|c| self.weight(mode, c, votes.get(&c.id).copied().unwrap_or(0), now))
+            .collect();
+        let total: f64 = weights.iter().sum();
+        if !total.is_normal() {
+            return Some(rng.gen_range(0..candidates.len()));
+        }
+        let mut threshold = rng.gen::<f64>() * total;
+        for (i, weight) in weights.into_iter().enumerate() {
+            if threshold < weight {
+                return Some(i);
+            }
+            threshold -= weight;
+        }
+        Some(candidates.len() - 1)
+    }
+}
+
+/// Picks up to `count` distinct random links matching the search, using the chat's selection mode
+pub fn pick_links(
+    database: &db::Database,
+    weights: &Weights,
+    mode: SelectionMode,
+    search: &Search,
+    count: usize,
+) -> db::errors::Result<Vec<model::Link>> {
+    if mode == SelectionMode::Uniform {
+        return database.fetch_random_links(search, count as i64);
+    }
+    let mut candidates = database.fetch_candidates(search)?;
+    let votes = if mode == SelectionMode::Weighted {
+        database.vote_totals()?.into_iter().collect()
+    } else {
+        HashMap::new()
+    };
+    let mut rng = rand::thread_rng();
+    let now = unix_now();
+    let mut picked = Vec::with_capacity(count);
+    while picked.len() < count {
+        let i = match weights.pick(&mut rng, mode, &candidates, &votes, now) {
+            Some(i) => i,
+            None => break,
+        };
+        picked.push(database.get_link(candidates.swap_remove(i).id)?);
+    }
+    Ok(picked)
+}
+
+#[test]
+fn test_selection_mode_from_str() {
+    for mode in SelectionMode::ALL.iter() {
+        assert_eq!(Ok(*mode), mode.as_str().parse());
+    }
+    assert!("random".parse::<SelectionMode>().is_err());
+}
+
+#[test]
+fn test_weighted_pick_favors_scores() {
+    use rand::SeedableRng;
+    let weights = Weights {
+        score_exponent: 1.,
+        vote_weight: 100.,
+        fresh_half_life_days: 30.,
+    };
+    let candidates = [
+        model::Candidate {
+            id: 1,
+            score: 0,
+            created_utc: 0,
+        },
+        model::Candidate {
+            id: 2,
+            score: 999,
+            created_utc: 0,
+        },
+    ];
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let no_votes = HashMap::new();
+    let picked_high = (0..1000)
+        .filter(|_| {
+            weights.pick(&mut rng, SelectionMode::Weighted, &candidates, &no_votes, 0) == Some(1)
+        })
+        .count();
+    assert!(picked_high > 950);
+    let votes = vec![(1, 50)].into_iter().collect();
+    let picked_voted = (0..1000)
+        .filter(|_| {
+            weights.pick(&mut rng, SelectionMode::Weighted, &candidates, &votes, 0) == Some(0)
+        })
+        .count();
+    assert!(picked_voted > 750);
+    assert_eq!(
+        None,
+        weights.pick(&mut rng, SelectionMode::Fresh, &[], &no_votes, 0)
+    );
+    let dated = [
+        candidates[0],
+        model::Candidate {
+            id: 3,
+            score: 0,
+            created_utc: 1_000_000_000,
+        },
+    ];
+    assert!((0..100).all(|_| {
+        weights.pick(
+            &mut rng,
+            SelectionMode::Fresh,
+            &dated,
+            &no_votes,
+            2_000_000_000,
+        ) == Some(1)
+    }));
+    assert_eq!(
+        Some(0),
+        weights.pick(
+            &mut rng,
+            SelectionMode::Fresh,
+            &candidates[..1],
+            &no_votes,
+            0
+        )
+    );
+}
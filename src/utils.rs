@@ -1,3 +1,5 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 pub fn utf8_pos_from_utf16(data: &str, pos: usize) -> Option<usize> {
     let mut pos_utf8 = 0_usize;
     let mut curr_pos_utf16 = 0_usize;
@@ -13,3 +15,10 @@ pub fn utf8_pos_from_utf16(data: &str, pos: usize) -> Option<usize> {
     }
     return None;
 }
+
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}